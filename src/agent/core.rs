@@ -0,0 +1,106 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::report::{PrefOutcome, StatusReport};
+
+/// The launchd label of the agent, also used as the plist file name.
+pub const AGENT_LABEL: &str = "com.machlit.cutler.agent";
+
+/// Returns the default directory for LaunchAgents (`~/Library/LaunchAgents`).
+pub fn default_agents_dir() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+
+    Ok(home.join("Library").join("LaunchAgents"))
+}
+
+/// Returns the path of the agent plist inside the given LaunchAgents directory.
+#[must_use]
+pub fn agent_plist_path(dir: &Path) -> PathBuf {
+    dir.join(format!("{AGENT_LABEL}.plist"))
+}
+
+/// Returns the directory cutler keeps its state in, i.e. `$XDG_STATE_HOME/cutler`,
+/// falling back to `~/.local/state/cutler`.
+pub fn get_state_dir() -> Result<PathBuf> {
+    let base = match dirs::state_dir() {
+        Some(dir) => dir,
+        None => dirs::home_dir()
+            .context("Could not determine home directory")?
+            .join(".local")
+            .join("state"),
+    };
+
+    Ok(base.join("cutler"))
+}
+
+/// Builds the LaunchAgent which runs `cutler agent run` every `interval` seconds.
+/// Output of each run is logged into the state directory.
+#[must_use]
+pub fn build_agent_plist(exe: &Path, interval: u64, state_dir: &Path) -> plist::Value {
+    let mut dict = plist::Dictionary::new();
+    let log = state_dir.join("agent.log").to_string_lossy().into_owned();
+
+    dict.insert("Label".into(), AGENT_LABEL.into());
+    dict.insert(
+        "ProgramArguments".into(),
+        plist::Value::Array(vec![
+            exe.to_string_lossy().into_owned().into(),
+            "agent".into(),
+            "run".into(),
+        ]),
+    );
+    dict.insert("StartInterval".into(), interval.into());
+    dict.insert("RunAtLoad".into(), true.into());
+    dict.insert("StandardOutPath".into(), log.clone().into());
+    dict.insert("StandardErrorPath".into(), log.into());
+
+    plist::Value::Dictionary(dict)
+}
+
+/// The report written by each `cutler agent run`.
+#[derive(Serialize, Debug)]
+pub struct AgentReport<'a> {
+    /// Seconds since the Unix epoch.
+    pub checked_at: u64,
+    /// Whether any preference had drifted from the config.
+    pub drifted: bool,
+    /// Preferences which were re-applied, as `domain | key`.
+    pub enforced: Vec<String>,
+    pub status: &'a StatusReport,
+}
+
+impl<'a> AgentReport<'a> {
+    #[must_use]
+    pub fn new(status: &'a StatusReport) -> Self {
+        let checked_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        Self {
+            checked_at,
+            drifted: status.preferences.iter().any(PrefOutcome::drifted),
+            enforced: Vec::new(),
+            status,
+        }
+    }
+
+    /// Writes the report as `report.json` into the state directory.
+    /// Returns the path written to.
+    pub async fn save(&self) -> Result<PathBuf> {
+        let dir = get_state_dir()?;
+        let path = dir.join("report.json");
+
+        tokio::fs::create_dir_all(&dir).await?;
+        tokio::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .await
+            .with_context(|| format!("Failed to write agent report to {path:?}"))?;
+
+        Ok(path)
+    }
+}
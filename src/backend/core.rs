@@ -0,0 +1,103 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env, fmt,
+    path::PathBuf,
+};
+
+use anyhow::Result;
+use defaults_rs::{Domain, PrefValue};
+
+use crate::{backend::file::FileBackend, cli::atomic::set_no_restart_services, log_info};
+
+/// Environment variable which, when set, points cutler to a directory of plist files
+/// to use instead of the live system preferences.
+pub const PREFS_DIR_ENV: &str = "CUTLER_PREFS_DIR";
+
+/// A preferences domain along with the host it is stored for.
+///
+/// Most preferences apply to any host, but some (menu bar items, screensaver, certain trackpad
+/// options) live in `~/Library/Preferences/ByHost` and are only read for the current machine,
+/// i.e. what `defaults -currentHost` targets.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PrefDomain {
+    pub domain: Domain,
+    pub current_host: bool,
+}
+
+impl fmt::Display for PrefDomain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.current_host {
+            write!(f, "{} (current host)", self.domain)
+        } else {
+            write!(f, "{}", self.domain)
+        }
+    }
+}
+
+/// Trait for implementing a preferences store which cutler can read from and write to.
+///
+/// Every command talks to preferences through this trait (carried inside `AppContext`),
+/// so that the same pipeline can run against the live system or a throwaway directory.
+pub trait PreferencesBackend: Send + Sync {
+    /// Short, human-readable name of the backend.
+    fn name(&self) -> &'static str;
+
+    /// Lists all (any-host) user domains known to the backend.
+    fn list_domains(&self) -> Result<HashSet<String>>;
+
+    /// Reads a single key from the given domain.
+    fn read(&self, domain: &PrefDomain, key: &str) -> Result<PrefValue>;
+
+    /// Reads every key-value pair of the given domain.
+    fn read_domain(&self, domain: &PrefDomain) -> Result<HashMap<String, PrefValue>>;
+
+    /// Writes a single key into the given domain, creating the domain if needed.
+    fn write(&self, domain: &PrefDomain, key: &str, value: &PrefValue) -> Result<()>;
+
+    /// Deletes a single key from the given domain.
+    fn delete(&self, domain: &PrefDomain, key: &str) -> Result<()>;
+}
+
+/// Turns an effective domain string into the domain which backends operate on.
+#[must_use]
+pub fn to_domain(eff_domain: &str, current_host: bool) -> PrefDomain {
+    let domain = if eff_domain == "NSGlobalDomain" {
+        Domain::Global
+    } else {
+        Domain::User(eff_domain.to_string())
+    };
+
+    PrefDomain {
+        domain,
+        current_host,
+    }
+}
+
+/// Decides the preferences backend for the current process.
+///
+/// If `CUTLER_PREFS_DIR` is set, a file-backed store rooted at that directory is used
+/// (and service restarts are disabled). Otherwise, the live system preferences are used (macOS only).
+pub fn get_prefs_backend() -> Result<Box<dyn PreferencesBackend>> {
+    if let Ok(dir) = env::var(PREFS_DIR_ENV)
+        && !dir.is_empty()
+    {
+        log_info!("Using file-backed preferences at: {dir}");
+
+        // there are no real services reading these preferences
+        set_no_restart_services(true);
+
+        return Ok(Box::new(FileBackend::new(PathBuf::from(dir))));
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        Ok(Box::new(crate::backend::system::SystemBackend))
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        anyhow::bail!(
+            "System preferences are only available on macOS. Set {PREFS_DIR_ENV} to use a file-backed store."
+        )
+    }
+}
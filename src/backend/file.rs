@@ -0,0 +1,130 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use defaults_rs::{Domain, PrefValue};
+
+use crate::{
+    backend::{PrefDomain, PreferencesBackend},
+    domains::convert::{plist_to_prefvalue, prefvalue_to_plist},
+};
+
+/// A preferences store backed by a directory of plist files.
+///
+/// Domains are laid out the same way as `~/Library/Preferences`, e.g. `com.apple.dock.plist`
+/// and `.GlobalPreferences.plist`, so a copy of a real preferences folder can be used as-is.
+/// Current-host domains are kept under `ByHost/`, without the hardware UUID suffix.
+pub struct FileBackend {
+    root: PathBuf,
+}
+
+impl FileBackend {
+    #[must_use]
+    pub const fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    #[must_use]
+    pub fn root(&self) -> &Path {
+        self.root.as_path()
+    }
+
+    /// Returns the plist file path for a domain.
+    fn domain_path(&self, domain: &PrefDomain) -> PathBuf {
+        let file = format!("{}.plist", domain.domain.get_cf_name());
+
+        if domain.current_host {
+            self.root.join("ByHost").join(file)
+        } else {
+            self.root.join(file)
+        }
+    }
+
+    /// Loads a whole domain file. A missing file is treated as an empty domain.
+    fn load(&self, domain: &PrefDomain) -> Result<plist::Dictionary> {
+        let path = self.domain_path(domain);
+
+        if !path.try_exists().unwrap_or(false) {
+            return Ok(plist::Dictionary::new());
+        }
+
+        plist::Value::from_file(&path)
+            .with_context(|| format!("Failed to read preferences file {path:?}."))?
+            .into_dictionary()
+            .ok_or_else(|| anyhow!("Preferences file {path:?} is not a dictionary at root."))
+    }
+
+    /// Saves a whole domain file.
+    fn store(&self, domain: &PrefDomain, dict: plist::Dictionary) -> Result<()> {
+        let path = self.domain_path(domain);
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        plist::Value::Dictionary(dict)
+            .to_file_xml(&path)
+            .with_context(|| format!("Failed to write preferences file {path:?}."))
+    }
+}
+
+impl PreferencesBackend for FileBackend {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn list_domains(&self) -> Result<HashSet<String>> {
+        let mut domains = HashSet::new();
+
+        if !self.root.try_exists().unwrap_or(false) {
+            return Ok(domains);
+        }
+
+        for entry in fs::read_dir(&self.root)? {
+            let path = entry?.path();
+
+            if path.extension().is_some_and(|ext| ext == "plist")
+                && let Some(stem) = path.file_stem().and_then(|s| s.to_str())
+                && stem != Domain::Global.get_cf_name()
+            {
+                domains.insert(stem.to_string());
+            }
+        }
+
+        Ok(domains)
+    }
+
+    fn read(&self, domain: &PrefDomain, key: &str) -> Result<PrefValue> {
+        match self.load(domain)?.get(key) {
+            Some(value) => plist_to_prefvalue(value),
+            None => bail!("Key {key} not found in domain {domain}."),
+        }
+    }
+
+    fn read_domain(&self, domain: &PrefDomain) -> Result<HashMap<String, PrefValue>> {
+        self.load(domain)?
+            .iter()
+            .map(|(k, v)| Ok((k.clone(), plist_to_prefvalue(v)?)))
+            .collect()
+    }
+
+    fn write(&self, domain: &PrefDomain, key: &str, value: &PrefValue) -> Result<()> {
+        let mut dict = self.load(domain)?;
+        dict.insert(key.to_string(), prefvalue_to_plist(value));
+
+        self.store(domain, dict)
+    }
+
+    fn delete(&self, domain: &PrefDomain, key: &str) -> Result<()> {
+        let mut dict = self.load(domain)?;
+
+        if dict.remove(key).is_none() {
+            bail!("Key {key} not found in domain {domain}.");
+        }
+
+        self.store(domain, dict)
+    }
+}
@@ -0,0 +1,6 @@
+mod core;
+pub mod file;
+#[cfg(target_os = "macos")]
+pub mod system;
+
+pub use core::*;
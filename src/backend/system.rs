@@ -0,0 +1,133 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use defaults_rs::{PrefValue, Preferences};
+
+use crate::{
+    backend::{PrefDomain, PreferencesBackend},
+    domains::convert::{plist_to_prefvalue, prefvalue_to_plist},
+};
+
+/// The live macOS preferences, accessed through `defaults_rs`.
+///
+/// `defaults_rs` only targets any-host preferences, so current-host domains are
+/// exported and imported as a whole through `defaults -currentHost`.
+pub struct SystemBackend;
+
+/// Reads a whole current-host domain through `defaults -currentHost export`.
+/// A domain without a ByHost plist yet is treated as an empty one.
+fn export_current_host(domain: &PrefDomain) -> Result<plist::Dictionary> {
+    let output = Command::new("defaults")
+        .args(["-currentHost", "export", &domain.domain.to_string(), "-"])
+        .output()
+        .context("Failed to run `defaults`.")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        if stderr.contains("does not exist") {
+            return Ok(plist::Dictionary::new());
+        }
+        bail!("Failed to export domain {domain}: {}", stderr.trim())
+    }
+
+    plist::Value::from_reader_xml(output.stdout.as_slice())
+        .with_context(|| format!("Failed to parse exported domain {domain}."))?
+        .into_dictionary()
+        .ok_or_else(|| anyhow!("Exported domain {domain} is not a dictionary."))
+}
+
+/// Replaces a whole current-host domain through `defaults -currentHost import`.
+fn import_current_host(domain: &PrefDomain, dict: plist::Dictionary) -> Result<()> {
+    let mut xml = Vec::new();
+    plist::Value::Dictionary(dict).to_writer_xml(&mut xml)?;
+
+    let mut child = Command::new("defaults")
+        .args(["-currentHost", "import", &domain.domain.to_string(), "-"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run `defaults`.")?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(&xml)?;
+    }
+
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        bail!(
+            "Failed to import domain {domain}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+    }
+
+    Ok(())
+}
+
+impl PreferencesBackend for SystemBackend {
+    fn name(&self) -> &'static str {
+        "system"
+    }
+
+    fn list_domains(&self) -> Result<HashSet<String>> {
+        Ok(Preferences::list_domains()?
+            .iter()
+            .map(|f| f.to_string())
+            .collect())
+    }
+
+    fn read(&self, domain: &PrefDomain, key: &str) -> Result<PrefValue> {
+        if domain.current_host {
+            return match export_current_host(domain)?.get(key) {
+                Some(value) => plist_to_prefvalue(value),
+                None => bail!("Key {key} not found in domain {domain}."),
+            };
+        }
+
+        Preferences::read(domain.domain.clone(), key)
+    }
+
+    fn read_domain(&self, domain: &PrefDomain) -> Result<HashMap<String, PrefValue>> {
+        if domain.current_host {
+            return export_current_host(domain)?
+                .iter()
+                .map(|(k, v)| Ok((k.clone(), plist_to_prefvalue(v)?)))
+                .collect();
+        }
+
+        match Preferences::read_domain(domain.domain.clone())? {
+            PrefValue::Dictionary(dict) => Ok(dict),
+            _ => bail!("Domain {domain} did not return a dictionary."),
+        }
+    }
+
+    fn write(&self, domain: &PrefDomain, key: &str, value: &PrefValue) -> Result<()> {
+        if domain.current_host {
+            let mut dict = export_current_host(domain)?;
+            dict.insert(key.to_string(), prefvalue_to_plist(value));
+
+            return import_current_host(domain, dict);
+        }
+
+        Preferences::write(domain.domain.clone(), key, value.clone())
+    }
+
+    fn delete(&self, domain: &PrefDomain, key: &str) -> Result<()> {
+        if domain.current_host {
+            let mut dict = export_current_host(domain)?;
+
+            if dict.remove(key).is_none() {
+                bail!("Key {key} not found in domain {domain}.");
+            }
+
+            return import_current_host(domain, dict);
+        }
+
+        Preferences::delete(domain.domain.clone(), key)
+    }
+}
@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 
 /// Represents the type of software to list in Homebrew.
@@ -26,7 +27,7 @@ impl Display for BrewListType {
 }
 
 /// Struct representing the diff between config and installed Homebrew state.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct BrewDiff {
     pub missing_formulae: Vec<String>,
     pub extra_formulae: Vec<String>,
@@ -204,12 +204,26 @@ pub async fn diff_brew(brew_cfg: Brew) -> Result<BrewDiff> {
         .cloned()
         .collect();
 
-    Ok(BrewDiff {
+    let mut diff = BrewDiff {
         missing_formulae,
         extra_formulae,
         missing_casks,
         extra_casks,
         missing_taps,
         extra_taps,
-    })
+    };
+
+    // keep the output stable across runs
+    for list in [
+        &mut diff.missing_formulae,
+        &mut diff.extra_formulae,
+        &mut diff.missing_casks,
+        &mut diff.extra_casks,
+        &mut diff.missing_taps,
+        &mut diff.extra_taps,
+    ] {
+        list.sort();
+    }
+
+    Ok(diff)
 }
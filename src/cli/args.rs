@@ -1,8 +1,11 @@
 use clap::{Parser, Subcommand};
 
+use crate::config::vars::parse_var_override;
+
 use crate::commands::{
-    ApplyCmd, BrewBackupCmd, BrewInstallCmd, CheckUpdateCmd, CompletionCmd, ConfigCmd, CookbookCmd,
-    ExecCmd, FetchCmd, InitCmd, LockCmd, ResetCmd, SelfUpdateCmd, StatusCmd, UnapplyCmd, UnlockCmd,
+    AgentInstallCmd, AgentRunCmd, AgentUninstallCmd, ApplyCmd, BrewBackupCmd, BrewInstallCmd,
+    CaptureCmd, CheckUpdateCmd, CompletionCmd, ConfigCmd, CookbookCmd, ExecCmd, FetchCmd, InitCmd,
+    LintCmd, LockCmd, PlanCmd, ResetCmd, SelfUpdateCmd, StatusCmd, UnapplyCmd, UnlockCmd, WatchCmd,
 };
 
 #[derive(Parser)]
@@ -47,6 +50,14 @@ pub struct Args {
     #[arg(long, global = true)]
     pub dry_run: bool,
 
+    /// The config profile to use (also read from CUTLER_PROFILE).
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+
+    /// Sets a variable, taking precedence over `[vars]` (e.g. `--var name=darkstar`).
+    #[arg(long = "var", global = true, value_name = "KEY=VALUE", value_parser = parse_var_override)]
+    pub vars: Vec<(String, String)>,
+
     /// Accepts all interactive prompts.
     #[arg(short = 'y', long, global = true)]
     pub accept_all: bool,
@@ -80,11 +91,24 @@ pub enum Command {
     /// Compare your system against config.
     #[command(visible_alias = "s")]
     Status(StatusCmd),
+    /// Save what `apply` would do for review.
+    Plan(PlanCmd),
+    /// Check the config against the built-in preference catalog.
+    Lint(LintCmd),
+    /// Write current system preferences of a domain into the config.
+    Capture(CaptureCmd),
+    /// Re-apply preferences whenever the config changes.
+    Watch(WatchCmd),
     /// Homebrew-related commands.
     Brew {
         #[command(subcommand)]
         command: BrewSubcmd,
     },
+    /// Periodic drift checks through a LaunchAgent.
+    Agent {
+        #[command(subcommand)]
+        command: AgentSubcmd,
+    },
     /// Shows the configuration.
     #[command(visible_alias = "conf")]
     Config(ConfigCmd),
@@ -102,6 +126,14 @@ pub enum Command {
     Fetch(FetchCmd),
 }
 
+#[derive(Subcommand, Debug)]
+pub enum ConfigSubcmd {
+    /// Remove the template marker so the config can be applied.
+    Activate,
+    /// Rewrite the config files into the current layout, keeping comments.
+    Migrate,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum BrewSubcmd {
     /// Backup current formulae/casks/taps into config.
@@ -110,3 +142,13 @@ pub enum BrewSubcmd {
     #[command(visible_alias = "apply")]
     Install(BrewInstallCmd),
 }
+
+#[derive(Subcommand, Debug)]
+pub enum AgentSubcmd {
+    /// Install a LaunchAgent which periodically runs `cutler agent run`.
+    Install(AgentInstallCmd),
+    /// Remove the installed LaunchAgent.
+    Uninstall(AgentUninstallCmd),
+    /// Check the system against the config and write a report.
+    Run(AgentRunCmd),
+}
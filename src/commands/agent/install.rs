@@ -0,0 +1,88 @@
+use std::{env, path::PathBuf};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use clap::Args;
+use tokio::{fs, process::Command};
+
+use crate::{
+    agent::{agent_plist_path, build_agent_plist, default_agents_dir, get_state_dir},
+    cli::atomic::should_dry_run,
+    commands::{Runnable, RunnableInvokeRules},
+    context::AppContext,
+    log_cute, log_dry, log_info, log_warn,
+};
+
+#[derive(Debug, Args)]
+pub struct AgentInstallCmd {
+    /// Seconds between each run of the agent.
+    #[arg(long, default_value_t = 3600)]
+    interval: u64,
+
+    /// The LaunchAgents directory to write into (defaults to ~/Library/LaunchAgents).
+    #[arg(long)]
+    dir: Option<PathBuf>,
+
+    /// Only write the plist, without loading it through `launchctl`.
+    #[arg(long)]
+    no_load: bool,
+}
+
+#[async_trait]
+impl Runnable for AgentInstallCmd {
+    fn set_invoke_rules(&self) -> RunnableInvokeRules {
+        RunnableInvokeRules {
+            do_config_autosync: false,
+            do_config_migrate: false,
+            require_sudo: false,
+            respect_lock: false,
+            respect_template: false,
+        }
+    }
+
+    async fn run(&self, _: &AppContext) -> Result<()> {
+        let dir = match &self.dir {
+            Some(dir) => dir.clone(),
+            None => default_agents_dir()?,
+        };
+        let path = agent_plist_path(&dir);
+
+        let exe = env::current_exe().context("Could not determine the path of cutler itself")?;
+        let state_dir = get_state_dir()?;
+        let plist = build_agent_plist(&exe, self.interval, &state_dir);
+
+        if should_dry_run() {
+            log_dry!("Would write LaunchAgent to {path:?}");
+            return Ok(());
+        }
+
+        fs::create_dir_all(&dir).await?;
+        fs::create_dir_all(&state_dir).await?;
+        plist
+            .to_file_xml(&path)
+            .with_context(|| format!("Failed to write LaunchAgent to {path:?}"))?;
+
+        log_info!("LaunchAgent written to {path:?}");
+
+        if !self.no_load {
+            let loaded = Command::new("launchctl")
+                .arg("load")
+                .arg("-w")
+                .arg(&path)
+                .status()
+                .await
+                .is_ok_and(|s| s.success());
+
+            if !loaded {
+                log_warn!("Failed to load the agent. Run `launchctl load -w {path:?}` manually.");
+            }
+        }
+
+        log_cute!(
+            "Agent installed; it will check the system every {} seconds.",
+            self.interval
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,3 @@
+pub mod install;
+pub mod run;
+pub mod uninstall;
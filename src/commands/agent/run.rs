@@ -0,0 +1,83 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use clap::Args;
+
+use crate::{
+    agent::AgentReport,
+    backend::to_domain,
+    commands::{ApplyCmd, Runnable, RunnableInvokeRules},
+    context::AppContext,
+    domains::DomainFilter,
+    log_cute, log_err, log_info, log_warn,
+    report::StatusReport,
+};
+
+#[derive(Debug, Args)]
+pub struct AgentRunCmd {
+    /// Disables Homebrew state check.
+    #[arg(long)]
+    no_brew: bool,
+
+    /// WARN: Disables domain existence check when enforcing.
+    #[arg(long)]
+    no_dom_check: bool,
+}
+
+#[async_trait]
+impl Runnable for AgentRunCmd {
+    fn set_invoke_rules(&self) -> RunnableInvokeRules {
+        RunnableInvokeRules {
+            do_config_autosync: true,
+            do_config_migrate: true,
+            require_sudo: false,
+            respect_lock: false,
+            respect_template: true,
+        }
+    }
+
+    async fn run(&self, ctx: &AppContext) -> Result<()> {
+        let filter = DomainFilter::default();
+        let status = StatusReport::gather(ctx, &filter, !self.no_brew).await?;
+        let mut report = AgentReport::new(&status);
+
+        if report.drifted {
+            let enforce = ctx
+                .config
+                .load()
+                .await?
+                .agent
+                .and_then(|a| a.enforce)
+                .unwrap_or_default();
+
+            if !enforce {
+                log_warn!("Preferences diverged. Set `enforce = true` under [agent] to re-apply.");
+            } else if ctx.config.is_locked().await {
+                log_warn!("Preferences diverged, but the config is locked; not enforcing.");
+            } else {
+                // the report is still written if enforcing fails
+                match ApplyCmd::prefs_only(filter, self.no_dom_check)
+                    .apply(ctx)
+                    .await
+                {
+                    Ok(jobs) => {
+                        for job in &jobs {
+                            let domain_obj = to_domain(&job.domain, job.current_host);
+                            log_info!("Enforced {domain_obj} | {}", job.key);
+                            report.enforced.push(format!("{domain_obj} | {}", job.key));
+                        }
+
+                        log_cute!("Re-applied {} drifted preference(s).", jobs.len());
+                    }
+                    Err(e) => log_err!("Failed to enforce the config: {e}"),
+                }
+            }
+        } else {
+            log_cute!("System preferences are on sync.");
+        }
+
+        let path = report.save().await?;
+        log_info!("Agent report written to {path:?}");
+
+        Ok(())
+    }
+}
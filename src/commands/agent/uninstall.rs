@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+use clap::Args;
+use tokio::{fs, process::Command};
+
+use crate::{
+    agent::{agent_plist_path, default_agents_dir},
+    cli::atomic::should_dry_run,
+    commands::{Runnable, RunnableInvokeRules},
+    context::AppContext,
+    log_cute, log_dry, log_warn,
+};
+
+#[derive(Debug, Args)]
+pub struct AgentUninstallCmd {
+    /// The LaunchAgents directory the agent was installed into (defaults to ~/Library/LaunchAgents).
+    #[arg(long)]
+    dir: Option<PathBuf>,
+
+    /// Only remove the plist, without unloading it through `launchctl`.
+    #[arg(long)]
+    no_load: bool,
+}
+
+#[async_trait]
+impl Runnable for AgentUninstallCmd {
+    fn set_invoke_rules(&self) -> RunnableInvokeRules {
+        RunnableInvokeRules {
+            do_config_autosync: false,
+            do_config_migrate: false,
+            require_sudo: false,
+            respect_lock: false,
+            respect_template: false,
+        }
+    }
+
+    async fn run(&self, _: &AppContext) -> Result<()> {
+        let dir = match &self.dir {
+            Some(dir) => dir.clone(),
+            None => default_agents_dir()?,
+        };
+        let path = agent_plist_path(&dir);
+
+        if !path.try_exists().unwrap_or_default() {
+            bail!("No agent is installed at {path:?}.")
+        }
+
+        if should_dry_run() {
+            log_dry!("Would remove LaunchAgent at {path:?}");
+            return Ok(());
+        }
+
+        if !self.no_load {
+            let unloaded = Command::new("launchctl")
+                .arg("unload")
+                .arg("-w")
+                .arg(&path)
+                .status()
+                .await
+                .is_ok_and(|s| s.success());
+
+            if !unloaded {
+                log_warn!("Failed to unload the agent; it may keep running until logout.");
+            }
+        }
+
+        fs::remove_file(&path).await?;
+        log_cute!("Agent uninstalled.");
+
+        Ok(())
+    }
+}
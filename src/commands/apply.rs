@@ -1,36 +1,32 @@
-use std::collections::{HashMap, HashSet};
+use std::{collections::HashSet, path::PathBuf};
 
 use crate::{
+    backend::{PreferencesBackend, to_domain},
     cli::atomic::should_dry_run,
     commands::{BrewInstallCmd, Runnable, RunnableInvokeRules},
     config::remote::RemoteConfigManager,
     context::AppContext,
-    domains::{
-        collect,
-        convert::{prefvalue_to_serializable, toml_to_prefvalue},
-        core::{self, get_sys_domain_strings},
-    },
+    domains::{DomainFilter, restart::services_for},
     exec::{ExecMode, run_all},
-    log_cute, log_dry, log_err, log_info, log_warn,
+    log_cute, log_dry, log_err, log_info,
+    plan::{Plan, PreferenceJob, compute_jobs, load_snapshot_for_apply},
     snapshot::core::SettingState,
-    util::{
-        io::{confirm, restart_services},
-        sha::get_digest,
-    },
+    util::io::{confirm, restart_services},
 };
-use anyhow::{Context, Result, bail};
+use anyhow::{Result, bail};
 use async_trait::async_trait;
 use clap::Args;
-use defaults_rs::{Domain, PrefValue, Preferences};
 
-use crate::domains::convert::SerializablePrefValue;
-
-#[derive(Args, Debug)]
+#[derive(Args, Debug, Default)]
 pub struct ApplyCmd {
     /// The URL to the remote config file.
     #[arg(short, long)]
     url: Option<String>,
 
+    /// Execute a plan saved by `cutler plan` instead of computing one.
+    #[arg(long, value_name = "FILE", conflicts_with_all = &["url", "no_cmd", "all_cmd", "flagged_cmd", "no_dom_check", "brew", "strict_types", "only", "exclude"])]
+    plan: Option<PathBuf>,
+
     /// Skip executing external commands.
     #[arg(short, long, conflicts_with_all = &["all_cmd", "flagged_cmd"])]
     no_cmd: bool,
@@ -62,15 +58,36 @@ pub struct ApplyCmd {
     /// When invoking `brew install`, skip formula installs.
     #[arg(long)]
     brew_skip_formula: bool,
+
+    /// Refuse to change the stored type of a preference.
+    #[arg(long)]
+    strict_types: bool,
+
+    /// Roll back every preference written in this run if any write fails.
+    #[arg(long)]
+    atomic: bool,
+
+    #[command(flatten)]
+    filter: DomainFilter,
 }
 
-/// Represents a preference modification job.
-#[derive(Debug)]
-struct PreferenceJob {
-    domain: String,
-    key: String,
-    original: Option<SerializablePrefValue>,
-    new_value: PrefValue,
+/// Restores the given (already written) jobs to the values they had before this run.
+fn rollback(prefs: &dyn PreferencesBackend, written: &[&PreferenceJob]) {
+    for job in written.iter().rev() {
+        let domain_obj = to_domain(&job.domain, job.current_host);
+
+        let result = if let Some(current) = &job.current {
+            log_info!("Rolling back {domain_obj} | {} -> {current}", job.key);
+            prefs.write(&domain_obj, &job.key, current)
+        } else {
+            log_info!("Rolling back {domain_obj} | {} (deleting)", job.key);
+            prefs.delete(&domain_obj, &job.key)
+        };
+
+        if let Err(e) = result {
+            log_err!("Failed to roll back {domain_obj} | {}: {e}", job.key);
+        }
+    }
 }
 
 #[async_trait]
@@ -78,188 +95,202 @@ impl Runnable for ApplyCmd {
     fn set_invoke_rules(&self) -> RunnableInvokeRules {
         RunnableInvokeRules {
             do_config_autosync: true,
+            do_config_migrate: true,
             require_sudo: false,
             respect_lock: true,
+            respect_template: self.url.is_none(),
         }
     }
 
     async fn run(&self, ctx: &AppContext) -> Result<()> {
-        let dry_run = should_dry_run();
-
-        // remote download logic
-        if let Some(url) = &self.url {
-            if ctx.config.is_loadable()
-                && !confirm("Local config exists but a URL was still passed. Proceed?")
-            {
-                bail!("Aborted apply: --url is passed despite local config.")
-            }
+        self.apply(ctx).await?;
+        log_cute!("Applying complete!");
 
-            let remote_mgr = RemoteConfigManager::new(url.to_owned());
-            remote_mgr.fetch().await?;
-            remote_mgr.save().await?;
+        Ok(())
+    }
+}
 
-            log_info!("Remote config downloaded at path: {:?}", ctx.config.path());
+impl ApplyCmd {
+    /// An apply of preferences only, without external commands or Homebrew.
+    #[must_use]
+    pub fn prefs_only(filter: DomainFilter, no_dom_check: bool) -> Self {
+        Self {
+            no_cmd: true,
+            no_dom_check,
+            filter,
+            ..Self::default()
         }
+    }
 
-        // parse + flatten domains
-        let digest = get_digest(ctx.config.path())?;
-        let doc = ctx.config.load_as_mut().await?;
-        let config_system_domains = collect(&doc).await?;
+    /// Runs the apply pipeline. Returns the preference jobs which were carried out
+    /// (or would have been, in dry-run mode).
+    pub async fn apply(&self, ctx: &AppContext) -> Result<Vec<PreferenceJob>> {
+        let dry_run = should_dry_run();
 
         // load the old snapshot (if any), otherwise create a new instance
-        let mut is_bad_snap: bool = false;
+        let (snap, is_bad_snap) = load_snapshot_for_apply(&ctx.snapshot).await;
 
-        let snap = if ctx.snapshot.is_loadable() {
-            match ctx.snapshot.load().await {
-                Ok(snap) => snap,
-                Err(e) => {
-                    log_warn!("Bad snapshot: {e}; starting new.");
-                    log_warn!("When unapplying, all your settings will reset to factory defaults.");
+        // either take the jobs from a saved plan, or compute them now
+        let (jobs, digest, run_brew, exec_mode) = if let Some(plan_path) = &self.plan {
+            let plan = Plan::load(plan_path).await?;
+            plan.verify(ctx).await?;
 
-                    is_bad_snap = true;
-                    ctx.snapshot.new_empty()
-                }
-            }
-        } else {
-            ctx.snapshot.new_empty()
-        };
+            log_info!("Executing plan from {plan_path:?}");
 
-        // ---
+            let names: Vec<String> = plan.commands.iter().map(|c| c.name.clone()).collect();
+            let exec_mode = (!names.is_empty()).then_some(ExecMode::Named(names));
 
-        // prepare for applying jobs
-        // this is going to be used for applying preferences and also saving them to snapshot
-        let mut jobs: Vec<PreferenceJob> = Vec::new();
-        let system_domains: HashSet<String> = get_sys_domain_strings()?;
+            // the snapshot keeps the digest of the config files, like a regular apply
+            (
+                plan.to_jobs(),
+                ctx.config.digest().await?,
+                plan.brew.is_some(),
+                exec_mode,
+            )
+        } else {
+            // remote download logic
+            if let Some(url) = &self.url {
+                if ctx.config.is_loadable()
+                    && !confirm("Local config exists but a URL was still passed. Proceed?")
+                {
+                    bail!("Aborted apply: --url is passed despite local config.")
+                }
 
-        // turn the old snapshot into a hashmap for a quick lookup
-        let mut existing: HashMap<_, _> = snap
-            .settings
-            .iter()
-            .map(|s| ((s.domain.clone(), s.key.clone()), s))
-            .collect();
+                let remote_mgr = RemoteConfigManager::new(url.to_owned());
+                remote_mgr.fetch().await?;
+                remote_mgr.save().await?;
 
-        // system-specific domains
-        for (dom, keyval_table) in config_system_domains {
-            for (key, toml_value) in keyval_table {
-                let (eff_dom, eff_key) = core::get_effective_sys_domain_key(&dom, &key);
+                log_info!("Remote config downloaded at path: {:?}", ctx.config.path());
 
-                if !self.no_dom_check
-                    && eff_dom != "NSGlobalDomain"
-                    && !system_domains.contains(&eff_dom)
-                {
+                if ctx.config.is_template().await {
                     bail!(
-                        "Domain \"{eff_dom}\" was not found; cannot write to it. Disable this behavior by passing: --no-dom-check"
+                        "Downloaded config is a template. Review it, then run `cutler config activate` to use it."
                     )
                 }
+            }
 
-                let current_pref = core::read_current(&eff_dom, &eff_key).await;
-                let new_pref = toml_to_prefvalue(&toml_value)?;
+            let digest = ctx.config.digest().await?;
+            let jobs = compute_jobs(
+                ctx,
+                &snap,
+                is_bad_snap,
+                &self.filter,
+                self.no_dom_check,
+                self.strict_types,
+            )
+            .await?;
 
-                // Compare PrefValues directly instead of strings
-                let changed = match &current_pref {
-                    Some(current) => current != &new_pref,
-                    None => true, // No current value means it's a new setting
-                };
+            (jobs, digest, self.brew, self.exec_mode())
+        };
 
-                // grab the old snapshot entry if it exists
-                let old_entry = existing.get(&(eff_dom.clone(), eff_key.clone())).cloned();
-
-                if changed {
-                    existing.remove(&(eff_dom.clone(), eff_key.clone()));
-
-                    // Preserve existing non-null original
-                    // otherwise, for brand new keys, capture original from system
-                    let original = if let Some(e) = &old_entry {
-                        e.original_value.clone()
-                    } else if let Some(pref) = current_pref {
-                        Some(prefvalue_to_serializable(&pref).with_context(|| {
-                            format!(
-                                "Failed to serialize current preference value for key '{eff_key}'."
-                            )
-                        })?)
-                    } else {
-                        None
-                    };
-
-                    jobs.push(PreferenceJob {
-                        domain: eff_dom,
-                        key: eff_key,
-                        new_value: new_pref,
-                        original: if is_bad_snap { None } else { original },
-                    });
-                } else {
-                    log_info!("Skipping unchanged {eff_dom} | {eff_key}",);
-                }
-            }
-        }
+        let mut applied = Vec::new();
 
         if dry_run {
+            applied.clone_from(&jobs);
+
             for job in &jobs {
-                log_dry!(
-                    "Would apply: {} {} -> {}",
-                    job.domain,
-                    job.key,
-                    job.new_value
-                );
+                if let Some(new_value) = &job.new_value {
+                    log_dry!(
+                        "Would apply: {} {} -> {new_value}",
+                        to_domain(&job.domain, job.current_host),
+                        job.key
+                    );
+                } else {
+                    log_dry!(
+                        "Would unset: {} {}",
+                        to_domain(&job.domain, job.current_host),
+                        job.key
+                    );
+                }
             }
         } else {
-            let mut applyable_settings_count = 0;
+            let prefs = ctx.prefs()?;
+            let mut written: Vec<&PreferenceJob> = Vec::new();
 
             for job in &jobs {
-                let domain_obj = if job.domain == "NSGlobalDomain" {
-                    Domain::Global
+                let domain_obj = to_domain(&job.domain, job.current_host);
+
+                let restorable = if let Some(orig) = &job.original {
+                    format!(
+                        "[Restorable to {}]",
+                        serde_json::to_string(orig).unwrap_or_else(|_| "?".to_string())
+                    )
                 } else {
-                    Domain::User(job.domain.clone())
+                    String::new()
                 };
 
-                log_info!(
-                    "Applying {} | {} -> {} {}",
-                    job.domain,
-                    job.key,
-                    job.new_value.to_string(),
-                    if let Some(orig) = &job.original {
-                        format!(
-                            "[Restorable to {}]",
-                            serde_json::to_string(orig).unwrap_or_else(|_| "?".to_string())
-                        )
-                    } else {
-                        String::new()
-                    }
-                );
+                let result = if let Some(new_value) = &job.new_value {
+                    log_info!(
+                        "Applying {} | {} -> {new_value} {restorable}",
+                        domain_obj,
+                        job.key,
+                    );
+                    prefs.write(&domain_obj, &job.key, new_value)
+                } else {
+                    log_info!("Unsetting {domain_obj} | {} {restorable}", job.key);
+                    prefs.delete(&domain_obj, &job.key)
+                };
 
-                if let Err(e) = Preferences::write(domain_obj, &job.key, job.new_value.clone()) {
+                if let Err(e) = result {
                     log_err!(
                         "Failed to apply preference ({} | {}). Error: {}",
-                        job.domain,
+                        domain_obj,
                         job.key,
                         e
                     );
+
+                    // in atomic mode, the first failure undoes the whole batch
+                    // the snapshot is left untouched since nothing was committed
+                    if self.atomic {
+                        rollback(prefs, &written);
+                        bail!(
+                            "Atomic apply aborted; rolled back {} written preference(s).",
+                            written.len()
+                        )
+                    }
                 } else {
-                    applyable_settings_count += 1;
+                    written.push(job);
                 }
             }
 
-            if applyable_settings_count > 0 {
-                log_info!(
-                    "Applied {} settings, will restart services.",
-                    applyable_settings_count
+            if !written.is_empty() {
+                log_info!("Applied {} settings, will restart services.", written.len());
+
+                let overrides = ctx.config.load().await.ok().and_then(|c| c.restart);
+                let services = services_for(
+                    written.iter().map(|j| j.domain.as_str()),
+                    overrides.as_ref(),
                 );
-                restart_services().await;
+                restart_services(&services).await;
             }
+
+            applied = written.into_iter().cloned().collect();
         }
 
         // prepare snapshot (old + new)
         let mut new_snap = ctx.snapshot.new_empty();
 
-        for ((_, _), old_entry) in existing {
-            new_snap.settings.push(old_entry.clone());
+        let touched: HashSet<(&str, &str, bool)> = jobs
+            .iter()
+            .map(|j| (j.domain.as_str(), j.key.as_str(), j.current_host))
+            .collect();
+
+        for old_entry in &snap.settings {
+            if !touched.contains(&(
+                old_entry.domain.as_str(),
+                old_entry.key.as_str(),
+                old_entry.current_host,
+            )) {
+                new_snap.settings.push(old_entry.clone());
+            }
         }
 
         for job in jobs {
             new_snap.settings.push(SettingState {
                 domain: job.domain,
                 key: job.key,
-                original_value: job.original.clone(),
+                current_host: job.current_host,
+                original_value: job.original,
             });
         }
 
@@ -274,7 +305,7 @@ impl Runnable for ApplyCmd {
         }
 
         // run brew
-        if self.brew {
+        if run_brew {
             BrewInstallCmd {
                 force: self.brew_force,
                 skip_cask: self.brew_skip_cask,
@@ -285,15 +316,7 @@ impl Runnable for ApplyCmd {
         }
 
         // exec external commands
-        if !self.no_cmd {
-            let mode = if self.all_cmd {
-                ExecMode::All
-            } else if self.flagged_cmd {
-                ExecMode::Flagged
-            } else {
-                ExecMode::Regular
-            };
-
+        if let Some(mode) = exec_mode {
             let loaded_config = ctx.config.load().await?;
             let exec_run_count = run_all(loaded_config, mode).await?;
 
@@ -307,8 +330,20 @@ impl Runnable for ApplyCmd {
             }
         }
 
-        log_cute!("Applying complete!");
+        Ok(applied)
+    }
 
-        Ok(())
+    /// Decides the external command execution mode from the passed flags.
+    /// Returns `None` if commands should be skipped.
+    fn exec_mode(&self) -> Option<ExecMode> {
+        if self.no_cmd {
+            None
+        } else if self.all_cmd {
+            Some(ExecMode::All)
+        } else if self.flagged_cmd {
+            Some(ExecMode::Flagged)
+        } else {
+            Some(ExecMode::Regular)
+        }
     }
 }
@@ -3,7 +3,7 @@ use std::collections::HashSet;
 use anyhow::Result;
 use async_trait::async_trait;
 use clap::Args;
-use toml_edit::{Array, DocumentMut, Item, Table, value};
+use toml_edit::{DocumentMut, Item, Table, Value, value};
 
 use crate::{
     brew::{
@@ -30,8 +30,10 @@ impl Runnable for BrewBackupCmd {
     fn set_invoke_rules(&self) -> RunnableInvokeRules {
         RunnableInvokeRules {
             do_config_autosync: false,
+            do_config_migrate: true,
             require_sudo: false,
             respect_lock: true,
+            respect_template: false,
         }
     }
 
@@ -42,12 +44,14 @@ impl Runnable for BrewBackupCmd {
         // ensure brew install
         ensure_brew().await?;
 
-        // init config
-        let mut doc = if let Ok(doc) = ctx.config.load_as_mut().await {
-            doc
+        // init config (or the included file which holds [brew])
+        let (path, mut doc) = if ctx.config.is_loadable() {
+            ctx.config
+                .load_owner_as_mut(|doc| doc.contains_key("brew"))
+                .await?
         } else {
             log_warn!("Configuration does not exist; a new one will be created.");
-            DocumentMut::new()
+            (ctx.config.path().to_path_buf(), DocumentMut::new())
         };
 
         let brew_item = doc.entry("brew").or_insert(Item::Table(Table::new()));
@@ -77,7 +81,6 @@ impl Runnable for BrewBackupCmd {
         }
 
         // load deps into memory for comparison
-        // this will also be reused for later comparisons
         let deps = if backup_no_deps {
             brew_list(BrewListType::Dependency).await?
         } else {
@@ -86,76 +89,128 @@ impl Runnable for BrewBackupCmd {
 
         // load the formulae, casks and taps list from the `brew` command
         // flattening is `false` since we want all names to be forced to --full-name
-        let formulas = brew_list(BrewListType::Formula).await?;
-        let casks = brew_list(BrewListType::Cask).await?;
-        let taps = brew_list(BrewListType::Tap).await?;
-
-        // build formulae and casks arrays
-        let mut formula_arr = Array::new();
-        for formula in &formulas {
-            if backup_no_deps {
-                if !deps.contains(formula) {
-                    if dry_run {
-                        log_dry!("Would push {formula} as a manually installed formula.",);
-                    } else {
-                        log_info!("Pushing {formula} as a manually installed formula.",);
-                        formula_arr.push(formula.clone());
-                    }
+        let manual = |items: HashSet<String>| -> HashSet<String> {
+            items
+                .into_iter()
+                .filter(|item| !backup_no_deps || !deps.contains(item))
+                .collect()
+        };
+        let lists = [
+            ("formulae", manual(brew_list(BrewListType::Formula).await?)),
+            ("casks", manual(brew_list(BrewListType::Cask).await?)),
+            ("taps", brew_list(BrewListType::Tap).await?),
+        ];
+
+        for (list, installed) in &lists {
+            let (added, removed) = backup_list(brew_tbl, list, installed);
+
+            for name in &added {
+                if dry_run {
+                    log_dry!("Would add {name} to {list}.");
+                } else {
+                    log_info!("Adding {name} to {list}.");
                 }
-            } else if dry_run {
-                log_dry!("Would push {formula}");
-            } else {
-                log_info!("Pushing {formula}");
-                formula_arr.push(formula.clone());
             }
-        }
-        log_info!("Pushed {} formulae.", formula_arr.len());
-        brew_tbl["formulae"] = value(formula_arr);
-
-        let mut cask_arr = Array::new();
-        for cask in &casks {
-            if backup_no_deps {
-                if !deps.contains(cask) {
-                    if dry_run {
-                        log_dry!("Would push {cask} as a manually installed cask.",);
-                    } else {
-                        log_info!("Pushing {cask} as a manually installed cask.",);
-                        cask_arr.push(cask.clone());
-                    }
+            for name in &removed {
+                if dry_run {
+                    log_dry!("Would remove {name} from {list} since it's no longer installed.");
+                } else {
+                    log_info!("Removing {name} from {list} since it's no longer installed.");
                 }
-            } else if dry_run {
-                log_dry!("Would push {cask}");
-            } else {
-                log_info!("Pushed {cask} as a cask.");
-                cask_arr.push(cask.clone());
             }
+            log_info!("Backed up {} {list}.", installed.len());
         }
-        log_info!("Pushed {} casks.", cask_arr.len());
-        brew_tbl["casks"] = value(cask_arr);
-
-        // backup taps
-        let mut taps_arr = Array::new();
-        for tap in &taps {
-            if dry_run {
-                log_dry!("Would push {tap} as tap.");
-            } else {
-                log_info!("Pushed {tap} as a tap.");
-                taps_arr.push(tap.clone());
-            }
-        }
-        log_info!("Pushed {} taps.", taps_arr.len());
-        brew_tbl["taps"] = value(taps_arr);
 
         // write backup
-        let path = ctx.config.path();
-
         if dry_run {
             log_info!("Backup would be saved to {:?}", path);
         } else {
-            doc.save(path).await?;
+            doc.save(&path).await?;
             log_cute!("Backup written to current configuration file.");
         }
 
         Ok(())
     }
 }
+
+/// Syncs a `[brew]` list with the installed items, returning the names added and removed.
+///
+/// Only plain entries are touched: the ones no longer installed are removed and newly installed
+/// items are added at the end. Conditional `{ name = "...", when = { ... } }` items are always
+/// kept as they are, since they may be meant for other machines.
+fn backup_list(
+    brew_tbl: &mut Table,
+    list: &str,
+    installed: &HashSet<String>,
+) -> (Vec<String>, Vec<String>) {
+    let mut items = brew_tbl
+        .get(list)
+        .and_then(Item::as_array)
+        .cloned()
+        .unwrap_or_default();
+
+    let mut removed = Vec::new();
+    items.retain(|item| match item.as_str() {
+        Some(name) if !installed.contains(name) => {
+            removed.push(name.to_string());
+            false
+        }
+        _ => true,
+    });
+
+    let listed: HashSet<String> = items
+        .iter()
+        .filter_map(|item| {
+            item.as_str().or_else(|| {
+                item.as_inline_table()
+                    .and_then(|tbl| tbl.get("name"))
+                    .and_then(Value::as_str)
+            })
+        })
+        .map(str::to_string)
+        .collect();
+
+    let mut added: Vec<String> = installed
+        .iter()
+        .filter(|name| !listed.contains(*name))
+        .cloned()
+        .collect();
+    added.sort();
+
+    for name in &added {
+        items.push(name.as_str());
+    }
+    brew_tbl[list] = value(items);
+
+    (added, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backup_keeps_conditional_items() -> Result<()> {
+        let mut doc = r#"
+[brew]
+formulae = ["git", "wget", { name = "mas", when = { hostname = "home-mbp" } }]
+"#
+        .parse::<DocumentMut>()?;
+        let installed = HashSet::from(["git".to_string(), "jq".to_string()]);
+
+        let brew_tbl = doc
+            .get_mut("brew")
+            .and_then(Item::as_table_mut)
+            .ok_or_else(|| anyhow::anyhow!("missing [brew]"))?;
+        let (added, removed) = backup_list(brew_tbl, "formulae", &installed);
+
+        assert_eq!(added, ["jq"]);
+        assert_eq!(removed, ["wget"]);
+        assert_eq!(
+            doc["brew"]["formulae"].to_string().trim(),
+            r#"["git", { name = "mas", when = { hostname = "home-mbp" } }, "jq"]"#
+        );
+
+        Ok(())
+    }
+}
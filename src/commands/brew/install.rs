@@ -34,8 +34,10 @@ impl Runnable for BrewInstallCmd {
     fn set_invoke_rules(&self) -> RunnableInvokeRules {
         RunnableInvokeRules {
             do_config_autosync: true,
+            do_config_migrate: true,
             require_sudo: false,
             respect_lock: true,
+            respect_template: true,
         }
     }
 
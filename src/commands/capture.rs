@@ -0,0 +1,230 @@
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use clap::Args;
+use toml_edit::{DocumentMut, Item, Table, value};
+
+use crate::{
+    backend::to_domain,
+    cli::atomic::should_dry_run,
+    commands::{Runnable, RunnableInvokeRules},
+    config::{ConfigCoreMethods, vars::doc_vars},
+    context::AppContext,
+    domains::{
+        convert::{prefvalue_to_toml, toml_edit_to_toml, toml_to_toml_edit},
+        core::{APP_PREFIX, CURRENT_HOST_PREFIX, get_effective_sys_domain_key, interpolate},
+    },
+    log_cute, log_dry, log_info, log_warn,
+};
+
+#[derive(Args, Debug)]
+pub struct CaptureCmd {
+    /// The domain to capture, written as in the config (e.g. `dock`, `app."org.mozilla.firefox"`).
+    domain: String,
+
+    /// The keys to capture. Captures the whole domain if none are given.
+    keys: Vec<String>,
+}
+
+#[async_trait]
+impl Runnable for CaptureCmd {
+    fn set_invoke_rules(&self) -> RunnableInvokeRules {
+        RunnableInvokeRules {
+            do_config_autosync: false,
+            do_config_migrate: true,
+            require_sudo: false,
+            respect_lock: true,
+            respect_template: false,
+        }
+    }
+
+    async fn run(&self, ctx: &AppContext) -> Result<()> {
+        let dry_run = should_dry_run();
+        let domain = self.domain.replace('"', "");
+
+        // for NSGlobalDomain.foo, the keys in the config lack the `foo.` prefix
+        let (eff_dom, key_prefix, current_host) = get_effective_sys_domain_key(&domain, "");
+        let domain_obj = to_domain(&eff_dom, current_host);
+
+        let live = ctx
+            .prefs()?
+            .read_domain(&domain_obj)
+            .map_err(|e| anyhow!("Failed to read {domain_obj}: {e}"))?;
+
+        let mut keys: Vec<String> = if self.keys.is_empty() {
+            live.keys()
+                .filter_map(|k| k.strip_prefix(&key_prefix))
+                .map(String::from)
+                .collect()
+        } else {
+            self.keys.clone()
+        };
+        keys.sort();
+
+        // init config (or the included file which already defines the domain)
+        let target = format!("set.{domain}");
+        let (path, mut doc) = if ctx.config.is_loadable() {
+            ctx.config
+                .load_owner_as_mut(|doc| find_table(doc.as_table_mut(), None, &target).is_some())
+                .await?
+        } else {
+            log_warn!("Configuration does not exist; a new one will be created.");
+            (ctx.config.path().to_path_buf(), DocumentMut::new())
+        };
+        let table = domain_table(&mut doc, &domain)?;
+
+        // keys may also be defined by other (included) files, which are left alone
+        let mut merged = ctx.config.load_as_mut().await.ok();
+        // values may use variables, which are compared expanded
+        let vars = merged
+            .as_ref()
+            .and_then(|m| doc_vars(m).ok())
+            .unwrap_or_default();
+        let matches = |v: &toml_edit::Value, new_value: &toml::Value| {
+            toml_edit_to_toml(v)
+                .and_then(|v| interpolate(v, &vars))
+                .is_ok_and(|v| v == *new_value)
+        };
+        let elsewhere = merged
+            .as_mut()
+            .and_then(|m| find_table(m.as_table_mut(), None, &target));
+
+        let mut captured = 0;
+
+        for key in keys {
+            let eff_key = format!("{key_prefix}{key}");
+
+            let Some(current) = live.get(&eff_key) else {
+                log_warn!("{domain_obj} | {eff_key} is not set, skipping.");
+                continue;
+            };
+            let new_value = match prefvalue_to_toml(current) {
+                Ok(v) => v,
+                Err(e) => {
+                    log_warn!("Cannot capture {domain_obj} | {eff_key}: {e}");
+                    continue;
+                }
+            };
+
+            if !table.contains_key(&key)
+                && let Some(other) = elsewhere.as_ref().and_then(|t| t.get(&key))
+            {
+                if other.as_value().is_some_and(|v| matches(v, &new_value)) {
+                    log_info!("{domain_obj} | {eff_key} is already in the config.");
+                } else {
+                    log_warn!("{domain_obj} | {eff_key} is set in another config file, skipping.");
+                }
+                continue;
+            }
+
+            // keep the trailing comment of a value already in the config
+            let existing = table.get(&key).and_then(Item::as_value);
+            if let Some(existing) = existing
+                && matches(existing, &new_value)
+            {
+                log_info!("{domain_obj} | {eff_key} is already in the config.");
+                continue;
+            }
+            let decor = existing.map(|v| v.decor().clone());
+
+            if dry_run {
+                log_dry!("Would capture {domain_obj} | {eff_key} = {new_value}");
+            } else {
+                log_info!("Capturing {domain_obj} | {eff_key} = {new_value}");
+            }
+
+            let mut new_value = toml_to_toml_edit(&new_value);
+            if let Some(decor) = decor {
+                *new_value.decor_mut() = decor;
+            }
+            table[&key] = value(new_value);
+            captured += 1;
+        }
+
+        if captured == 0 {
+            log_cute!("Nothing to capture; the config is up to date.");
+            return Ok(());
+        }
+
+        if dry_run {
+            log_info!("Captured preferences would be saved to {:?}", path);
+        } else {
+            doc.save(&path).await?;
+            log_cute!("Captured {captured} preference(s) into the config.");
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the `[set]` table of a domain, creating it (e.g. `[set.menuextra.clock]`) if missing.
+fn domain_table<'a>(doc: &'a mut DocumentMut, domain: &str) -> Result<&'a mut Table> {
+    let target = format!("set.{domain}");
+
+    if find_table(doc.as_table_mut(), None, &target).is_none() {
+        let mut tbl = doc.as_table_mut();
+
+        for name in table_path(domain) {
+            tbl = tbl
+                .entry(name)
+                .or_insert_with(|| {
+                    let mut t = Table::new();
+                    t.set_implicit(true);
+                    Item::Table(t)
+                })
+                .as_table_mut()
+                .ok_or_else(|| anyhow!("Cannot capture into {target}: `{name}` is not a table."))?;
+        }
+        tbl.set_implicit(false);
+    }
+
+    find_table(doc.as_table_mut(), None, &target)
+        .ok_or_else(|| anyhow!("Cannot capture into {target}."))
+}
+
+/// Looks up a table by its dotted name, however it's split up in the config.
+fn find_table<'a>(
+    table: &'a mut Table,
+    prefix: Option<&str>,
+    target: &str,
+) -> Option<&'a mut Table> {
+    for (key, item) in table.iter_mut() {
+        let Item::Table(t) = item else { continue };
+        let name = match prefix {
+            Some(prefix) => format!("{prefix}.{}", key.get()),
+            None => key.get().to_string(),
+        };
+
+        if name == target {
+            return Some(t);
+        }
+        if target.starts_with(&format!("{name}."))
+            && let Some(found) = find_table(t, Some(&name), target)
+        {
+            return Some(found);
+        }
+    }
+
+    None
+}
+
+/// Splits a domain into the table names it's written as, e.g. `app.org.mozilla.firefox`
+/// into `set`, `app` and `org.mozilla.firefox`.
+fn table_path(domain: &str) -> Vec<&str> {
+    let mut path = vec!["set"];
+
+    let domain = match domain.strip_prefix(CURRENT_HOST_PREFIX) {
+        Some(rest) => {
+            path.push("currenthost");
+            rest
+        }
+        None => domain,
+    };
+
+    if let Some(bundle_id) = domain.strip_prefix(APP_PREFIX) {
+        path.extend(["app", bundle_id]);
+    } else {
+        path.extend(domain.split('.'));
+    }
+
+    path
+}
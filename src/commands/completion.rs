@@ -35,8 +35,10 @@ impl Runnable for CompletionCmd {
     fn set_invoke_rules(&self) -> RunnableInvokeRules {
         RunnableInvokeRules {
             do_config_autosync: false,
+            do_config_migrate: false,
             require_sudo: false,
             respect_lock: false,
+            respect_template: false,
         }
     }
 
@@ -7,76 +7,151 @@ use clap::Args;
 use tokio::fs;
 
 use crate::{
-    cli::atomic::should_dry_run,
+    cli::{args::ConfigSubcmd, atomic::should_dry_run},
     commands::{Runnable, RunnableInvokeRules},
+    config::{
+        ConfigCoreMethods,
+        migrate::{CONFIG_VERSION, migrate_files},
+    },
     context::AppContext,
     log_cute, log_dry, log_info,
 };
 
 #[derive(Debug, Args)]
-pub struct ConfigCmd;
+pub struct ConfigCmd {
+    #[command(subcommand)]
+    command: Option<ConfigSubcmd>,
+}
 
 #[async_trait]
 impl Runnable for ConfigCmd {
     fn set_invoke_rules(&self) -> RunnableInvokeRules {
         RunnableInvokeRules {
             do_config_autosync: false,
+            do_config_migrate: false,
             require_sudo: false,
             respect_lock: true,
+            respect_template: false,
         }
     }
 
     async fn run(&self, ctx: &AppContext) -> Result<()> {
+        match self.command {
+            Some(ConfigSubcmd::Activate) => activate(ctx).await,
+            Some(ConfigSubcmd::Migrate) => migrate(ctx).await,
+            None => show(ctx).await,
+        }
+    }
+}
+
+/// Strips the template marker from the config.
+async fn activate(ctx: &AppContext) -> Result<()> {
+    if !ctx.config.is_loadable() {
+        bail!("Cannot find a configuration to activate in the first place.")
+    }
+
+    if !ctx.config.is_template().await {
+        log_cute!("Config is already active.");
+        return Ok(());
+    }
+
+    if should_dry_run() {
+        log_dry!(
+            "Would remove the template marker from {:?}",
+            ctx.config.path()
+        );
+        return Ok(());
+    }
+
+    ctx.config.activate().await?;
+    log_cute!("Config activated. Run `cutler apply` to apply it.");
+
+    Ok(())
+}
+
+/// Rewrites the config files into the current layout.
+async fn migrate(ctx: &AppContext) -> Result<()> {
+    if !ctx.config.is_loadable() {
+        bail!("Cannot find a configuration to migrate in the first place.")
+    }
+
+    let files = migrate_files(&ctx.config).await?;
+
+    if files.is_empty() {
+        log_cute!("Config is already at version {CONFIG_VERSION}.");
+        return Ok(());
+    }
+
+    for file in &files {
+        for change in &file.changes {
+            log_info!("{:?}: {change}", file.path);
+        }
+
         if should_dry_run() {
-            log_dry!("Would display config from {:?}", ctx.config.path());
-            return Ok(());
+            log_dry!("Would save migrated config to {:?}", file.path);
+        } else {
+            file.doc.save(&file.path).await?;
         }
+    }
+
+    if !should_dry_run() {
+        log_cute!("Config migrated to version {CONFIG_VERSION}.");
+    }
+
+    Ok(())
+}
+
+/// Shows the config, inside $EDITOR if available.
+async fn show(ctx: &AppContext) -> Result<()> {
+    if should_dry_run() {
+        log_dry!("Would display config from {:?}", ctx.config.path());
+        return Ok(());
+    }
 
-        // show inside editor if available
-        let editor = env::var("EDITOR");
-
-        if let Ok(editor_cmd) = editor {
-            // split the editor command into program and args, respecting quoted arguments
-            let parsed = shell_words::split(&editor_cmd);
-            let (program, args) = match parsed {
-                Ok(mut parts) if !parts.is_empty() => {
-                    let prog = parts.remove(0);
-                    (prog, parts)
-                }
-                Ok(_) => {
-                    bail!("EDITOR environment variable is empty.");
-                }
-                Err(e) => {
-                    bail!("Failed to parse EDITOR: {e}");
-                }
-            };
-
-            log_info!("Executing: {} {:?}", editor_cmd, ctx.config.path());
-            log_cute!("Opening configuration in editor. Close editor to quit.",);
-            let mut command = Command::new(program);
-            command.args(&args).arg(ctx.config.path());
-
-            let status = command.status();
-            match status {
-                Ok(s) if s.success() => {
-                    log_info!("Opened configuration file in editor.");
-                }
-                Ok(s) => {
-                    bail!("Editor exited with status: {s}");
-                }
-                Err(e) => {
-                    bail!("Failed to launch editor: {e}");
-                }
+    // show inside editor if available
+    let editor = env::var("EDITOR");
+
+    if let Ok(editor_cmd) = editor {
+        // split the editor command into program and args, respecting quoted arguments
+        let parsed = shell_words::split(&editor_cmd);
+        let (program, args) = match parsed {
+            Ok(mut parts) if !parts.is_empty() => {
+                let prog = parts.remove(0);
+                (prog, parts)
             }
-        } else {
-            log_info!("Editor could not be found, opening normally:\n",);
+            Ok(_) => {
+                bail!("EDITOR environment variable is empty.");
+            }
+            Err(e) => {
+                bail!("Failed to parse EDITOR: {e}");
+            }
+        };
 
-            // read and print the file
-            let content = fs::read_to_string(ctx.config.path()).await?;
+        log_info!("Executing: {} {:?}", editor_cmd, ctx.config.path());
+        log_cute!("Opening configuration in editor. Close editor to quit.",);
+        let mut command = Command::new(program);
+        command.args(&args).arg(ctx.config.path());
 
-            println!("{content}");
+        let status = command.status();
+        match status {
+            Ok(s) if s.success() => {
+                log_info!("Opened configuration file in editor.");
+            }
+            Ok(s) => {
+                bail!("Editor exited with status: {s}");
+            }
+            Err(e) => {
+                bail!("Failed to launch editor: {e}");
+            }
         }
+    } else {
+        log_info!("Editor could not be found, opening normally:\n",);
 
-        Ok(())
+        // read and print the file
+        let content = fs::read_to_string(ctx.config.path()).await?;
+
+        println!("{content}");
     }
+
+    Ok(())
 }
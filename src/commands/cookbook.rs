@@ -16,8 +16,10 @@ impl Runnable for CookbookCmd {
     fn set_invoke_rules(&self) -> RunnableInvokeRules {
         RunnableInvokeRules {
             do_config_autosync: false,
+            do_config_migrate: false,
             require_sudo: false,
             respect_lock: false,
+            respect_template: false,
         }
     }
 
@@ -26,8 +26,10 @@ impl Runnable for ExecCmd {
     fn set_invoke_rules(&self) -> RunnableInvokeRules {
         RunnableInvokeRules {
             do_config_autosync: true,
+            do_config_migrate: true,
             require_sudo: false,
             respect_lock: true,
+            respect_template: true,
         }
     }
 
@@ -26,8 +26,10 @@ impl Runnable for FetchCmd {
     fn set_invoke_rules(&self) -> RunnableInvokeRules {
         RunnableInvokeRules {
             do_config_autosync: false,
+            do_config_migrate: true,
             require_sudo: false,
             respect_lock: true,
+            respect_template: false,
         }
     }
 
@@ -11,15 +11,21 @@ use crate::{
 };
 
 #[derive(Args, Debug)]
-pub struct InitCmd;
+pub struct InitCmd {
+    /// Strip the `template = true` marker so the new config can be applied right away.
+    #[arg(long)]
+    activate: bool,
+}
 
 #[async_trait]
 impl Runnable for InitCmd {
     fn set_invoke_rules(&self) -> RunnableInvokeRules {
         RunnableInvokeRules {
             do_config_autosync: false,
+            do_config_migrate: false,
             require_sudo: false,
             respect_lock: true,
+            respect_template: false,
         }
     }
 
@@ -50,10 +56,17 @@ impl Runnable for InitCmd {
         .await?;
         fs::write(ctx.config.path(), default_cfg).await?;
 
-        log_cute!(
-            "Config created at {:?}, Review and customize it before applying.",
-            ctx.config.path()
-        );
+        if self.activate {
+            ctx.config.activate().await?;
+
+            log_cute!("Config created and activated at {:?}", ctx.config.path());
+        } else {
+            log_cute!(
+                "Config created at {:?}, Review and customize it before applying.",
+                ctx.config.path()
+            );
+            log_cute!("Once done, run `cutler config activate` to start using it.");
+        }
 
         Ok(())
     }
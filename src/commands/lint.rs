@@ -0,0 +1,179 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+use clap::Args;
+use toml::Table;
+use toml_edit::DocumentMut;
+
+use crate::{
+    commands::{Runnable, RunnableInvokeRules},
+    context::AppContext,
+    domains::{
+        ValueSpec,
+        catalog::{Catalog, suggest},
+        collect, collect_inapplicable,
+        core::get_effective_sys_domain_key,
+    },
+    log_cute, log_info, log_warn,
+};
+
+#[derive(Args, Debug)]
+pub struct LintCmd;
+
+#[async_trait]
+impl Runnable for LintCmd {
+    fn set_invoke_rules(&self) -> RunnableInvokeRules {
+        RunnableInvokeRules {
+            do_config_autosync: false,
+            do_config_migrate: true,
+            require_sudo: false,
+            respect_lock: false,
+            respect_template: false,
+        }
+    }
+
+    async fn run(&self, ctx: &AppContext) -> Result<()> {
+        let catalog = Catalog::get()?;
+        let doc = ctx.config.load_as_mut().await?;
+
+        let issues = lint(catalog, &doc).await?;
+        for issue in &issues {
+            log_warn!("{issue}");
+        }
+
+        if !issues.is_empty() {
+            bail!("Found {} issue(s) in the config.", issues.len())
+        }
+
+        log_cute!("No issues found.");
+
+        Ok(())
+    }
+}
+
+/// Checks the `[set]` domains against the catalog, returning the issues found.
+/// Domains whose `when` clause doesn't hold on this machine are checked as well.
+pub async fn lint(catalog: &Catalog, doc: &DocumentMut) -> Result<Vec<String>> {
+    let mut all: HashMap<String, Table> = collect(doc).await?;
+    for (dom, table) in collect_inapplicable(doc).await? {
+        all.entry(dom).or_default().extend(table);
+    }
+
+    let mut domains: Vec<_> = all.into_iter().collect();
+    domains.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut issues = Vec::new();
+    let mut unknown_domains = HashSet::new();
+
+    for (dom, table) in domains {
+        for (key, value) in table {
+            let (eff_dom, eff_key, _) = get_effective_sys_domain_key(&dom, &key);
+
+            let spec = match ValueSpec::from_toml(&value) {
+                Ok(spec) => spec,
+                Err(e) => {
+                    issues.push(format!("{eff_dom} | {eff_key}: invalid value: {e}"));
+                    continue;
+                }
+            };
+
+            let Some(keys) = catalog.domain(&eff_dom) else {
+                // compare the names as written in the config, without the implied prefix
+                let written = eff_dom.strip_prefix("com.apple.").unwrap_or(&eff_dom);
+                let candidates = catalog
+                    .domain_names()
+                    .map(|d| d.strip_prefix("com.apple.").unwrap_or(d));
+
+                if let Some(similar) = suggest(written, candidates) {
+                    // only report each mistyped domain once
+                    if unknown_domains.insert(eff_dom.clone()) {
+                        issues.push(format!(
+                            "{eff_dom}: unknown domain. Did you mean {similar}?"
+                        ));
+                    }
+                } else {
+                    log_info!("{eff_dom} | {eff_key}: not in catalog, skipping");
+                }
+                continue;
+            };
+
+            let Some(entry) = keys.get(&eff_key) else {
+                if let Some(similar) = suggest(&eff_key, keys.keys().map(String::as_str)) {
+                    issues.push(format!(
+                        "{eff_dom} | {eff_key}: unknown key. Did you mean {similar}?"
+                    ));
+                } else {
+                    log_info!("{eff_dom} | {eff_key}: not in catalog, skipping");
+                }
+                continue;
+            };
+
+            // partial forms (merges, array operations) are only checked for their type
+            let problem = match &spec {
+                ValueSpec::Replace(pref) => entry.check(pref),
+                _ => {
+                    let kind = spec.resolve(None).get_type();
+                    (!entry.accepts_type(kind))
+                        .then(|| format!("expected {}, found {kind}", entry.kind))
+                }
+            };
+
+            if let Some(problem) = problem {
+                issues.push(format!(
+                    "{eff_dom} | {eff_key}: {problem} ({})",
+                    entry.description
+                ));
+            } else {
+                log_info!("{eff_dom} | {eff_key}: ok");
+            }
+        }
+    }
+
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn shipped_examples_lint_clean() -> Result<()> {
+        let examples = [
+            include_str!("../../examples/basic.toml"),
+            include_str!("../../examples/brew.toml"),
+            include_str!("../../examples/complete.toml"),
+            include_str!("../../examples/advanced_types.toml"),
+        ];
+
+        for example in examples {
+            let issues = lint(Catalog::get()?, &example.parse()?).await?;
+
+            assert!(issues.is_empty(), "{issues:#?}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invalid_values_and_inapplicable_domains_are_reported() -> Result<()> {
+        let doc = r#"
+[set.dock]
+tilesize = { "$merge" = 1 }
+autohid = true
+
+[set.finder]
+when = { hostname = "cutler-no-such-host" }
+ShowPathbr = true
+"#
+        .parse()?;
+        let issues = lint(Catalog::get()?, &doc).await?;
+
+        assert_eq!(issues.len(), 3, "{issues:#?}");
+        assert!(issues[0].starts_with("com.apple.dock | autohid: unknown key"));
+        assert!(issues[1].starts_with("com.apple.dock | tilesize: invalid value"));
+        assert!(issues[2].starts_with("com.apple.finder | ShowPathbr: unknown key"));
+
+        Ok(())
+    }
+}
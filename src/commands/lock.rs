@@ -19,8 +19,10 @@ impl Runnable for LockCmd {
     fn set_invoke_rules(&self) -> RunnableInvokeRules {
         RunnableInvokeRules {
             do_config_autosync: false,
+            do_config_migrate: false,
             require_sudo: true,
             respect_lock: false,
+            respect_template: false,
         }
     }
 
@@ -29,7 +31,7 @@ impl Runnable for LockCmd {
             bail!("Cannot find a configuration to lock in the first place.")
         }
 
-        let mut document = ctx.config.load_as_mut().await?;
+        let mut document = ctx.config.load_main_as_mut().await?;
         let dry_run = should_dry_run();
 
         if document
@@ -1,8 +1,10 @@
 use anyhow::Result;
 use async_trait::async_trait;
 
+pub mod agent;
 pub mod apply;
 pub mod brew;
+pub mod capture;
 pub mod check_update;
 pub mod completion;
 pub mod config;
@@ -10,15 +12,20 @@ pub mod cookbook;
 pub mod exec;
 pub mod fetch;
 pub mod init;
+pub mod lint;
 pub mod lock;
+pub mod plan;
 pub mod reset;
 pub mod self_update;
 pub mod status;
 pub mod unapply;
 pub mod unlock;
+pub mod watch;
 
+pub use agent::{install::AgentInstallCmd, run::AgentRunCmd, uninstall::AgentUninstallCmd};
 pub use apply::ApplyCmd;
 pub use brew::{backup::BrewBackupCmd, install::BrewInstallCmd};
+pub use capture::CaptureCmd;
 pub use check_update::CheckUpdateCmd;
 pub use completion::CompletionCmd;
 pub use config::ConfigCmd;
@@ -26,12 +33,15 @@ pub use cookbook::CookbookCmd;
 pub use exec::ExecCmd;
 pub use fetch::FetchCmd;
 pub use init::InitCmd;
+pub use lint::LintCmd;
 pub use lock::LockCmd;
+pub use plan::PlanCmd;
 pub use reset::ResetCmd;
 pub use self_update::SelfUpdateCmd;
 pub use status::StatusCmd;
 pub use unapply::UnapplyCmd;
 pub use unlock::UnlockCmd;
+pub use watch::WatchCmd;
 
 use crate::{cli::Command, context::AppContext};
 
@@ -60,6 +70,10 @@ impl Command {
             Command::Unapply(unapply_cmd) => unapply_cmd.run(ctx).await,
             Command::Reset(reset_cmd) => reset_cmd.run(ctx).await,
             Command::Status(status_cmd) => status_cmd.run(ctx).await,
+            Command::Plan(plan_cmd) => plan_cmd.run(ctx).await,
+            Command::Lint(lint_cmd) => lint_cmd.run(ctx).await,
+            Command::Capture(capture_cmd) => capture_cmd.run(ctx).await,
+            Command::Watch(watch_cmd) => watch_cmd.run(ctx).await,
             Command::Brew { command } => match command {
                 crate::cli::args::BrewSubcmd::Backup(brew_backup_cmd) => {
                     brew_backup_cmd.run(ctx).await
@@ -68,6 +82,15 @@ impl Command {
                     brew_install_cmd.run(ctx).await
                 }
             },
+            Command::Agent { command } => match command {
+                crate::cli::args::AgentSubcmd::Install(agent_install_cmd) => {
+                    agent_install_cmd.run(ctx).await
+                }
+                crate::cli::args::AgentSubcmd::Uninstall(agent_uninstall_cmd) => {
+                    agent_uninstall_cmd.run(ctx).await
+                }
+                crate::cli::args::AgentSubcmd::Run(agent_run_cmd) => agent_run_cmd.run(ctx).await,
+            },
             Command::Config(config_cmd) => config_cmd.run(ctx).await,
             Command::CheckUpdate(check_update_cmd) => check_update_cmd.run(ctx).await,
             Command::SelfUpdate(self_update_cmd) => self_update_cmd.run(ctx).await,
@@ -88,6 +111,10 @@ impl Command {
             Command::Unapply(unapply_cmd) => unapply_cmd.set_invoke_rules(),
             Command::Reset(reset_cmd) => reset_cmd.set_invoke_rules(),
             Command::Status(status_cmd) => status_cmd.set_invoke_rules(),
+            Command::Plan(plan_cmd) => plan_cmd.set_invoke_rules(),
+            Command::Lint(lint_cmd) => lint_cmd.set_invoke_rules(),
+            Command::Capture(capture_cmd) => capture_cmd.set_invoke_rules(),
+            Command::Watch(watch_cmd) => watch_cmd.set_invoke_rules(),
             Command::Brew { command } => match command {
                 crate::cli::args::BrewSubcmd::Backup(brew_backup_cmd) => {
                     brew_backup_cmd.set_invoke_rules()
@@ -96,6 +123,17 @@ impl Command {
                     brew_install_cmd.set_invoke_rules()
                 }
             },
+            Command::Agent { command } => match command {
+                crate::cli::args::AgentSubcmd::Install(agent_install_cmd) => {
+                    agent_install_cmd.set_invoke_rules()
+                }
+                crate::cli::args::AgentSubcmd::Uninstall(agent_uninstall_cmd) => {
+                    agent_uninstall_cmd.set_invoke_rules()
+                }
+                crate::cli::args::AgentSubcmd::Run(agent_run_cmd) => {
+                    agent_run_cmd.set_invoke_rules()
+                }
+            },
             Command::Config(config_cmd) => config_cmd.set_invoke_rules(),
             Command::CheckUpdate(check_update_cmd) => check_update_cmd.set_invoke_rules(),
             Command::SelfUpdate(self_update_cmd) => self_update_cmd.set_invoke_rules(),
@@ -109,8 +147,12 @@ impl Command {
 pub struct RunnableInvokeRules {
     /// Whether to autosync configuration with cloud before command invocation.
     pub do_config_autosync: bool,
+    /// Whether to offer migrating a config written in an older layout before command invocation.
+    pub do_config_migrate: bool,
     /// Whether the command requires sudo privileges for execution.
     pub require_sudo: bool,
     /// Whether to respect a locked configuration file.
     pub respect_lock: bool,
+    /// Whether to refuse running on a template configuration (`template = true`).
+    pub respect_template: bool,
 }
@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+use clap::Args;
+
+use crate::{
+    brew::utils::{brew_is_installed, diff_brew},
+    cli::atomic::should_dry_run,
+    commands::{Runnable, RunnableInvokeRules},
+    context::AppContext,
+    domains::DomainFilter,
+    exec::{ExecMode, list_cmds},
+    log_cute, log_dry, log_info,
+    plan::{Plan, PlannedCommand, compute_jobs, load_snapshot_for_apply},
+    util::logging::redact,
+};
+
+#[derive(Args, Debug)]
+pub struct PlanCmd {
+    /// Write the plan to the given file instead of printing it.
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Leave external commands out of the plan.
+    #[arg(short, long, conflicts_with_all = &["all_cmd", "flagged_cmd"])]
+    no_cmd: bool,
+
+    /// Plan all external commands (even flagged ones).
+    #[arg(short, long, conflicts_with_all = &["no_cmd", "flagged_cmd"])]
+    all_cmd: bool,
+
+    /// Plan flagged external commands only.
+    #[arg(short, long, conflicts_with_all = &["all_cmd", "no_cmd"])]
+    flagged_cmd: bool,
+
+    /// WARN: Disables domain existence check.
+    #[arg(long)]
+    no_dom_check: bool,
+
+    /// Include missing Homebrew formulae/casks/taps in the plan.
+    #[arg(short, long)]
+    brew: bool,
+
+    /// Refuse to change the stored type of a preference.
+    #[arg(long)]
+    strict_types: bool,
+
+    #[command(flatten)]
+    filter: DomainFilter,
+}
+
+#[async_trait]
+impl Runnable for PlanCmd {
+    fn set_invoke_rules(&self) -> RunnableInvokeRules {
+        RunnableInvokeRules {
+            do_config_autosync: true,
+            do_config_migrate: true,
+            require_sudo: false,
+            respect_lock: false,
+            respect_template: false,
+        }
+    }
+
+    async fn run(&self, ctx: &AppContext) -> Result<()> {
+        let digest = ctx.config.resolved_digest().await?;
+        let config = ctx.config.load().await?;
+
+        // preferences
+        let (snap, is_bad_snap) = load_snapshot_for_apply(&ctx.snapshot).await;
+        let jobs = compute_jobs(
+            ctx,
+            &snap,
+            is_bad_snap,
+            &self.filter,
+            self.no_dom_check,
+            self.strict_types,
+        )
+        .await?;
+
+        // homebrew
+        let brew = if self.brew {
+            let Some(brew_cfg) = config.brew.clone() else {
+                bail!("No [brew] section found in config")
+            };
+
+            if !brew_is_installed().await {
+                bail!("Homebrew is required for planning installs, but was not found.")
+            }
+
+            Some(diff_brew(brew_cfg).await?)
+        } else {
+            None
+        };
+
+        // external commands
+        let commands = if self.no_cmd {
+            Vec::new()
+        } else {
+            let mode = if self.all_cmd {
+                ExecMode::All
+            } else if self.flagged_cmd {
+                ExecMode::Flagged
+            } else {
+                ExecMode::Regular
+            };
+
+            list_cmds(&config, &mode)
+                .into_iter()
+                .map(|(name, run)| PlannedCommand {
+                    name,
+                    run: redact(&run),
+                })
+                .collect()
+        };
+
+        let plan = Plan::new(digest, &jobs, brew, commands)?;
+
+        for p in &plan.preferences {
+            log_info!(
+                "Planned {} | {} -> {}",
+                p.domain,
+                p.key,
+                serde_json::to_string(&p.desired).unwrap_or_else(|_| "?".to_string())
+            );
+        }
+
+        if let Some(path) = &self.output {
+            if should_dry_run() {
+                log_dry!("Would save plan to {path:?}");
+            } else {
+                plan.save(path).await?;
+                log_cute!(
+                    "Planned {} preference change(s) and {} command(s); saved to {path:?}",
+                    plan.preferences.len(),
+                    plan.commands.len()
+                );
+            }
+        } else {
+            println!("{}", plan.to_redacted_json()?);
+        }
+
+        Ok(())
+    }
+}
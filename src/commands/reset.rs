@@ -1,28 +1,38 @@
+use std::collections::BTreeSet;
+
 use anyhow::Result;
 use async_trait::async_trait;
 use clap::Args;
-use defaults_rs::{Domain, Preferences};
 use tokio::fs;
 
 use crate::{
+    backend::to_domain,
     cli::atomic::should_dry_run,
     commands::{Runnable, RunnableInvokeRules},
     context::AppContext,
-    domains::{collect, core::get_effective_sys_domain_key, read_current},
+    domains::{
+        DomainFilter, collect, core::get_effective_sys_domain_key, read_current,
+        restart::services_for,
+    },
     log_cute, log_dry, log_err, log_info, log_warn,
     util::io::{confirm, restart_services},
 };
 
-#[derive(Args, Debug)]
-pub struct ResetCmd;
+#[derive(Args, Debug, Default)]
+pub struct ResetCmd {
+    #[command(flatten)]
+    pub filter: DomainFilter,
+}
 
 #[async_trait]
 impl Runnable for ResetCmd {
     fn set_invoke_rules(&self) -> RunnableInvokeRules {
         RunnableInvokeRules {
             do_config_autosync: false,
+            do_config_migrate: true,
             require_sudo: false,
             respect_lock: true,
+            respect_template: true,
         }
     }
 
@@ -38,25 +48,33 @@ impl Runnable for ResetCmd {
 
         let doc = ctx.config.load_as_mut().await?;
         let config_system_domains = collect(&doc).await?;
+        let prefs = ctx.prefs()?;
+        let mut touched = BTreeSet::new();
 
         for (dom, table) in config_system_domains {
             for (key, _) in table {
-                let (eff_dom, eff_key) = get_effective_sys_domain_key(&dom, &key);
+                let (eff_dom, eff_key, current_host) = get_effective_sys_domain_key(&dom, &key);
+
+                if !self.filter.matches(&eff_dom, &eff_key, current_host) {
+                    log_info!("Skipping filtered {eff_dom}.{eff_key}");
+                    continue;
+                }
 
                 // only delete it if currently set
-                if read_current(&eff_dom, &eff_key).await.is_some() {
-                    let domain_obj = if eff_dom == "NSGlobalDomain" {
-                        Domain::Global
-                    } else {
-                        Domain::User(eff_dom.clone())
-                    };
+                if read_current(prefs, &eff_dom, &eff_key, current_host)
+                    .await
+                    .is_some()
+                {
+                    let domain_obj = to_domain(&eff_dom, current_host);
 
                     if dry_run {
                         log_dry!("Would reset {eff_dom}.{eff_key} to system default",);
+                        touched.insert(eff_dom);
                     } else {
-                        match Preferences::delete(domain_obj, &eff_key) {
+                        match prefs.delete(&domain_obj, &eff_key) {
                             Ok(()) => {
                                 log_info!("Reset {eff_dom}.{eff_key} to system default");
+                                touched.insert(eff_dom);
                             }
                             Err(e) => {
                                 log_err!("Failed to reset {eff_dom}.{eff_key}: {e}");
@@ -70,9 +88,25 @@ impl Runnable for ResetCmd {
         }
 
         // remove snapshot if present
+        // when filtering, only the matching entries are dropped from it
         let snap_path = ctx.snapshot.path();
 
-        if ctx.snapshot.is_loadable() {
+        if self.filter.is_active() {
+            if ctx.snapshot.is_loadable()
+                && let Ok(mut snap) = ctx.snapshot.load().await
+            {
+                snap.settings
+                    .retain(|s| !self.filter.matches(&s.domain, &s.key, s.current_host));
+
+                if dry_run {
+                    log_dry!("Would remove reset entries from snapshot at {snap_path:?}");
+                } else if let Err(e) = snap.save().await {
+                    log_warn!("Failed to update snapshot: {e}");
+                } else {
+                    log_info!("Removed reset entries from snapshot at {snap_path:?}");
+                }
+            }
+        } else if ctx.snapshot.is_loadable() {
             if dry_run {
                 log_dry!("Would remove snapshot at {snap_path:?}",);
             } else if let Err(e) = fs::remove_file(&snap_path).await {
@@ -82,10 +116,16 @@ impl Runnable for ResetCmd {
             }
         }
 
-        log_cute!("Reset complete. All configured settings have been removed.",);
+        if self.filter.is_active() {
+            log_cute!("Reset complete. The matching configured settings have been removed.");
+        } else {
+            log_cute!("Reset complete. All configured settings have been removed.");
+        }
 
         // restart system services if requested
-        restart_services().await;
+        let overrides = ctx.config.load().await?.restart;
+        let services = services_for(touched.iter().map(String::as_str), overrides.as_ref());
+        restart_services(&services).await;
 
         log_cute!("Reset complete.");
 
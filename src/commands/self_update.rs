@@ -23,8 +23,10 @@ impl Runnable for SelfUpdateCmd {
     fn set_invoke_rules(&self) -> RunnableInvokeRules {
         RunnableInvokeRules {
             do_config_autosync: false,
+            do_config_migrate: false,
             require_sudo: true,
             respect_lock: false,
+            respect_template: false,
         }
     }
 
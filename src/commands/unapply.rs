@@ -1,30 +1,35 @@
+use std::collections::BTreeSet;
+
 use anyhow::{Result, bail};
 use async_trait::async_trait;
 use clap::Args;
-use defaults_rs::{Domain, PrefValue, Preferences};
+use defaults_rs::PrefValue;
 
 use crate::{
+    backend::{PrefDomain, to_domain},
     cli::atomic::should_dry_run,
     commands::{ResetCmd, Runnable, RunnableInvokeRules},
     context::AppContext,
-    domains::convert::serializable_to_prefvalue,
+    domains::{DomainFilter, convert::serializable_to_prefvalue, restart::services_for},
     log_cute, log_dry, log_err, log_info, log_warn,
-    util::{
-        io::{confirm, restart_services},
-        sha::get_digest,
-    },
+    util::io::{confirm, restart_services},
 };
 
 #[derive(Args, Debug)]
-pub struct UnapplyCmd;
+pub struct UnapplyCmd {
+    #[command(flatten)]
+    filter: DomainFilter,
+}
 
 #[async_trait]
 impl Runnable for UnapplyCmd {
     fn set_invoke_rules(&self) -> RunnableInvokeRules {
         RunnableInvokeRules {
             do_config_autosync: true,
+            do_config_migrate: true,
             require_sudo: false,
             respect_lock: true,
+            respect_template: false,
         }
     }
 
@@ -33,7 +38,11 @@ impl Runnable for UnapplyCmd {
             log_warn!("No snapshot found to revert.");
 
             if confirm("Reset all System Settings instead?") {
-                return ResetCmd.run(ctx).await;
+                return ResetCmd {
+                    filter: self.filter.clone(),
+                }
+                .run(ctx)
+                .await;
             }
             bail!("Abort operation.")
         }
@@ -41,7 +50,7 @@ impl Runnable for UnapplyCmd {
         let dry_run = should_dry_run();
 
         // load snapshot from disk
-        let snapshot = match ctx.snapshot.load().await {
+        let mut snapshot = match ctx.snapshot.load().await {
             Ok(snap) => snap,
             Err(_) => {
                 bail!(
@@ -51,22 +60,25 @@ impl Runnable for UnapplyCmd {
             }
         };
 
-        if snapshot.digest != get_digest(ctx.config.path())? {
+        if snapshot.digest != ctx.config.digest().await? {
             log_warn!("Config has been modified since last application.",);
             log_warn!("Please note that only the applied modifications will be unapplied.",);
         }
 
+        // only the entries passing the filters are unapplied; the rest stay in the snapshot
+        let (targets, kept): (Vec<_>, Vec<_>) = snapshot
+            .settings
+            .clone()
+            .into_iter()
+            .partition(|s| self.filter.matches(&s.domain, &s.key, s.current_host));
+
         // prepare undo operations, grouping by domain for efficiency
-        let mut restore_jobs: Vec<(Domain, String, PrefValue)> = Vec::new();
-        let mut delete_jobs: Vec<(Domain, String)> = Vec::new();
+        let mut restore_jobs: Vec<(PrefDomain, String, PrefValue)> = Vec::new();
+        let mut delete_jobs: Vec<(PrefDomain, String)> = Vec::new();
 
         // reverse order to undo in correct sequence
-        for s in snapshot.settings.clone().into_iter().rev() {
-            let domain_obj = if s.domain == "NSGlobalDomain" {
-                Domain::Global
-            } else {
-                Domain::User(s.domain.clone())
-            };
+        for s in targets.into_iter().rev() {
+            let domain_obj = to_domain(&s.domain, s.current_host);
 
             if let Some(orig) = s.original_value {
                 let pref_value = serializable_to_prefvalue(&orig);
@@ -86,18 +98,27 @@ impl Runnable for UnapplyCmd {
                 log_dry!("Would delete setting: {domain} | {key}",);
             }
 
-            log_dry!("Would delete snapshot at path: {:?}", ctx.snapshot.path());
+            if kept.is_empty() {
+                log_dry!("Would delete snapshot at path: {:?}", ctx.snapshot.path());
+            } else {
+                log_dry!(
+                    "Would keep {} unmatched setting(s) in snapshot at path: {:?}",
+                    kept.len(),
+                    ctx.snapshot.path()
+                );
+            }
         } else {
-            let mut settings_modified_count = 0;
+            let prefs = ctx.prefs()?;
+            let mut touched = BTreeSet::new();
 
             if !restore_jobs.is_empty() {
                 for (domain, key, value) in restore_jobs {
                     log_info!("Restoring: {domain} | {key} -> {value}",);
 
-                    if let Err(e) = Preferences::write(domain.clone(), &key, value.clone()) {
+                    if let Err(e) = prefs.write(&domain, &key, &value) {
                         log_err!("Restore failed: {e}");
                     } else {
-                        settings_modified_count += 1;
+                        touched.insert(domain.domain.to_string());
                     }
                 }
             }
@@ -106,30 +127,105 @@ impl Runnable for UnapplyCmd {
                 for (domain, key) in delete_jobs {
                     log_info!("Deleting: {domain} | {key}");
 
-                    if let Err(e) = Preferences::delete(domain.clone(), &key) {
+                    if let Err(e) = prefs.delete(&domain, &key) {
                         log_err!("Delete failed: {e}");
                     } else {
-                        settings_modified_count += 1;
+                        touched.insert(domain.domain.to_string());
                     }
                 }
             }
 
-            if snapshot.exec_run_count > 0 {
+            if kept.is_empty() && snapshot.exec_run_count > 0 {
                 log_warn!(
                     "{} commands were executed previously; revert them manually.",
                     snapshot.exec_run_count
                 );
             }
 
-            if settings_modified_count > 0 {
-                log_info!("Modified {settings_modified_count} settings; restarting services.");
-                restart_services().await;
+            if !touched.is_empty() {
+                log_info!("Modified {} domain(s); restarting services.", touched.len());
+
+                let overrides = ctx.config.load().await.ok().and_then(|c| c.restart);
+                let services = services_for(touched.iter().map(String::as_str), overrides.as_ref());
+                restart_services(&services).await;
             }
 
-            snapshot.delete().await?;
+            if kept.is_empty() {
+                snapshot.delete().await?;
+            } else {
+                snapshot.settings = kept;
+                snapshot.save().await?;
+                log_info!(
+                    "Kept {} unmatched setting(s) in snapshot.",
+                    snapshot.settings.len()
+                );
+            }
             log_cute!("Unapply operation complete.");
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use anyhow::Result;
+    use tokio::fs;
+
+    use super::*;
+    use crate::{
+        backend::{PREFS_DIR_ENV, PreferencesBackend, file::FileBackend},
+        commands::ApplyCmd,
+        config::Config,
+        report::StatusReport,
+        snapshot::Snapshot,
+    };
+
+    #[tokio::test]
+    async fn apply_status_unapply_round_trip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let prefs_dir = dir.path().join("prefs");
+
+        // SAFETY: no other test reads or writes this variable
+        unsafe { env::set_var(PREFS_DIR_ENV, &prefs_dir) };
+
+        let config_path = dir.path().join("config.toml");
+        fs::write(
+            &config_path,
+            "version = 1\n\n[set.dock]\ntilesize = 46\nautohide = true\n",
+        )
+        .await?;
+
+        let ctx = AppContext::new(
+            Config::new(config_path),
+            Snapshot::new(dir.path().join("snapshot.json")),
+        );
+
+        // one key already set, one missing
+        let dock = to_domain("com.apple.dock", false);
+        let prefs = FileBackend::new(prefs_dir);
+        prefs.write(&dock, "tilesize", &PrefValue::Integer(64))?;
+
+        ApplyCmd::prefs_only(DomainFilter::default(), true)
+            .apply(&ctx)
+            .await?;
+        assert_eq!(prefs.read(&dock, "tilesize")?, PrefValue::Integer(46));
+        assert_eq!(prefs.read(&dock, "autohide")?, PrefValue::Boolean(true));
+
+        let report = StatusReport::gather(&ctx, &DomainFilter::default(), false).await?;
+        assert!(!report.has_drift());
+
+        UnapplyCmd {
+            filter: DomainFilter::default(),
+        }
+        .run(&ctx)
+        .await?;
+        assert_eq!(prefs.read(&dock, "tilesize")?, PrefValue::Integer(64));
+        assert!(prefs.read(&dock, "autohide").is_err());
+        assert!(!ctx.snapshot.is_loadable());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use clap::Args;
+use tokio::time::sleep;
+
+use crate::{
+    backend::to_domain,
+    cli::atomic::{set_quiet, should_be_quiet, should_dry_run},
+    commands::{ApplyCmd, Runnable, RunnableInvokeRules},
+    context::AppContext,
+    domains::DomainFilter,
+    log_cute, log_dry, log_err, log_info, log_warn,
+    plan::PreferenceJob,
+};
+
+/// How often the config file is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Args, Debug)]
+pub struct WatchCmd {
+    /// Milliseconds the config has to stay unchanged before it is applied.
+    #[arg(long, default_value_t = 500)]
+    debounce: u64,
+
+    /// WARN: Disables domain existence check.
+    #[arg(long)]
+    no_dom_check: bool,
+
+    #[command(flatten)]
+    filter: DomainFilter,
+}
+
+#[async_trait]
+impl Runnable for WatchCmd {
+    fn set_invoke_rules(&self) -> RunnableInvokeRules {
+        RunnableInvokeRules {
+            do_config_autosync: false,
+            do_config_migrate: true,
+            require_sudo: false,
+            respect_lock: true,
+            respect_template: true,
+        }
+    }
+
+    async fn run(&self, ctx: &AppContext) -> Result<()> {
+        let path = ctx.config.path();
+        let mut last = ctx.config.digest().await?;
+
+        log_cute!("Watching {path:?} for changes. Press Ctrl-C to stop.");
+
+        loop {
+            sleep(POLL_INTERVAL).await;
+
+            // a file may briefly disappear while an editor saves it
+            let Ok(digest) = ctx.config.digest().await else {
+                continue;
+            };
+            if digest == last {
+                continue;
+            }
+
+            // wait for the config to settle before applying
+            let digest = self.settle(ctx, digest).await;
+            last = digest;
+
+            log_info!("Config changed, applying.");
+            self.apply(ctx).await;
+        }
+    }
+}
+
+impl WatchCmd {
+    /// Waits until the config digest stays the same for the debounce period, returning it.
+    async fn settle(&self, ctx: &AppContext, mut digest: String) -> String {
+        loop {
+            sleep(Duration::from_millis(self.debounce)).await;
+
+            match ctx.config.digest().await {
+                Ok(next) if next != digest => digest = next,
+                _ => return digest,
+            }
+        }
+    }
+
+    /// Runs the apply pipeline quietly, then prints the keys which changed.
+    /// Errors are only reported, so that a half-written config doesn't stop the watch.
+    /// Locked and template configs are skipped, like they are at startup.
+    async fn apply(&self, ctx: &AppContext) {
+        // the config may have been locked or turned into a template since the watch started
+        if ctx.config.is_locked().await {
+            log_warn!("Config is locked; skipping. Run `cutler config unlock` to unlock.");
+            return;
+        }
+        if ctx.config.is_template().await {
+            log_warn!("Config is a template; skipping. Run `cutler config activate` to use it.");
+            return;
+        }
+
+        let cmd = ApplyCmd::prefs_only(self.filter.clone(), self.no_dom_check);
+
+        let was_quiet = should_be_quiet();
+        set_quiet(true);
+        let result = cmd.apply(ctx).await;
+        set_quiet(was_quiet);
+
+        match result {
+            Ok(jobs) if jobs.is_empty() => {
+                log_cute!("Config changed; preferences already on sync.")
+            }
+            Ok(jobs) => {
+                for job in &jobs {
+                    print_change(job);
+                }
+            }
+            Err(e) => log_err!("Failed to apply the config: {e}"),
+        }
+    }
+}
+
+/// Prints a single changed key along with its old and new value.
+fn print_change(job: &PreferenceJob) {
+    let domain_obj = to_domain(&job.domain, job.current_host);
+    let old = job
+        .current
+        .as_ref()
+        .map_or_else(|| "(unset)".to_string(), ToString::to_string);
+    let new = job
+        .new_value
+        .as_ref()
+        .map_or_else(|| "(unset)".to_string(), ToString::to_string);
+
+    if should_dry_run() {
+        log_dry!("Would change {domain_obj} | {}: {old} -> {new}", job.key);
+    } else {
+        log_cute!("{domain_obj} | {}: {old} -> {new}", job.key);
+    }
+}
@@ -3,12 +3,23 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use anyhow::{Context, Result, bail};
+use anyhow::{Context, Result, anyhow, bail};
 use serde::Deserialize;
 use tokio::fs;
 use toml::Value;
 use toml_edit::DocumentMut;
 
+use crate::{
+    config::{
+        include::{merge_includes, resolve_includes},
+        migrate::{config_version, migrate},
+        profile::apply_profile,
+        vars::{Var, doc_vars},
+        when::{When, resolve_brew_items},
+    },
+    util::sha::{get_combined_digest, get_parts_digest},
+};
+
 /// Struct representing a loaded cutler configuration.
 ///
 /// This is a fully serde-compatible struct primarily meant to be used within cutler's source code
@@ -16,21 +27,28 @@ use toml_edit::DocumentMut;
 #[derive(Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct LoadedConfig {
+    pub version: Option<i64>,
     pub lock: Option<bool>,
     pub template: Option<bool>,
+    pub include: Option<Vec<String>>,
     pub set: Option<HashMap<String, HashMap<String, Value>>>,
-    pub vars: Option<HashMap<String, String>>,
+    pub unset: Option<toml::Table>,
+    pub vars: Option<HashMap<String, Var>>,
     pub command: Option<HashMap<String, Command>>,
     pub brew: Option<Brew>,
     pub remote: Option<Remote>,
+    pub restart: Option<HashMap<String, Vec<String>>>,
+    pub agent: Option<Agent>,
+    pub profile: Option<HashMap<String, Profile>>,
     #[serde(skip)]
     pub path: PathBuf,
 }
 
-/// Struct representing a lock-field-only configuration file.
+/// Struct representing a marker-fields-only (`lock`, `template`) configuration file.
 #[derive(Deserialize, Default)]
-pub struct LockOnlyLoadedConfig {
+pub struct MarkerOnlyLoadedConfig {
     pub lock: Option<bool>,
+    pub template: Option<bool>,
 }
 
 /// Represents the [remote] table.
@@ -50,6 +68,7 @@ pub struct Command {
     pub required: Option<Vec<String>>,
     pub flag: Option<bool>,
     pub sudo: Option<bool>,
+    pub when: Option<When>,
 }
 
 /// Represents the [brew] table.
@@ -62,6 +81,28 @@ pub struct Brew {
     pub no_deps: Option<bool>,
 }
 
+/// Represents the [agent] table.
+#[derive(Deserialize, PartialEq, Eq, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Agent {
+    /// Re-apply drifted preferences on each `cutler agent run`.
+    pub enforce: Option<bool>,
+}
+
+/// Represents [profile.***] tables.
+///
+/// The selected profile is overlaid onto the base config when loading,
+/// so only the fields which may be partial are kept as raw tables here.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub hostnames: Option<Vec<String>>,
+    pub set: Option<toml::Table>,
+    pub brew: Option<toml::Table>,
+    pub command: Option<toml::Table>,
+    pub vars: Option<HashMap<String, Var>>,
+}
+
 /// Represents an unloaded cutler configuration.
 ///
 /// This must be loaded with .load() to return a LoadedConfig, or .load_as_mut() to return a toml_edit::DocumentMut.
@@ -85,49 +126,154 @@ impl Config {
         !self.path.as_os_str().is_empty() && self.path.try_exists().unwrap_or_default()
     }
 
-    /// Basic config wrapper for checking the lock state.
-    pub async fn is_locked(&self) -> bool {
+    /// Reads only the marker fields of the config, defaulting if not loadable.
+    async fn load_markers(&self) -> MarkerOnlyLoadedConfig {
         if self.is_loadable() {
             let data = fs::read_to_string(&self.path).await;
 
             match data {
-                Ok(data) => {
-                    let cfg: LockOnlyLoadedConfig = toml::from_str(&data).unwrap_or_default();
-                    cfg.lock.unwrap_or_default()
-                }
-                Err(_) => false,
+                Ok(data) => toml::from_str(&data).unwrap_or_default(),
+                Err(_) => MarkerOnlyLoadedConfig::default(),
             }
         } else {
-            false
+            MarkerOnlyLoadedConfig::default()
         }
     }
 
-    /// Loads the configuration. Errors out if the configuration is not loadable
-    /// (decided by `.is_loadable()`).
-    pub async fn load(&self) -> Result<LoadedConfig> {
-        if self.is_loadable() {
-            let data = fs::read_to_string(&self.path).await?;
+    /// Basic config wrapper for checking the lock state.
+    pub async fn is_locked(&self) -> bool {
+        self.load_markers().await.lock.unwrap_or_default()
+    }
 
-            let mut config: LoadedConfig =
-                toml::from_str(&data).context("Failed to parse config data from valid TOML.")?;
-            config.path = self.path.to_owned();
+    /// Basic config wrapper for checking whether the config is still a template.
+    pub async fn is_template(&self) -> bool {
+        self.load_markers().await.template.unwrap_or_default()
+    }
 
-            Ok(config)
-        } else {
-            bail!("Config path does not exist!")
+    /// Strips the `template` marker (and its leading comment) from the config.
+    /// Returns `false` if the config was not a template in the first place.
+    pub async fn activate(&self) -> Result<bool> {
+        let mut doc = self.load_main_as_mut().await?;
+
+        if doc.remove("template").is_none() {
+            return Ok(false);
         }
+
+        doc.save(&self.path).await?;
+        Ok(true)
     }
 
-    /// Loads config as mutable `DocumentMut`. Useful for in-place editing of values.
+    /// Loads the configuration, along with all included files. Errors out if the configuration
+    /// is not loadable (decided by `.is_loadable()`).
+    pub async fn load(&self) -> Result<LoadedConfig> {
+        let doc = self.load_as_mut().await?;
+
+        let mut config: LoadedConfig = toml::from_str(&doc.to_string())
+            .context("Failed to parse config data from valid TOML.")?;
+        config.path = self.path.to_owned();
+
+        Ok(config)
+    }
+
+    /// Loads config as `DocumentMut`, with all included files and the selected profile merged in.
+    /// Configs written in an older layout are migrated on the fly.
+    ///
+    /// Since other files and tables are merged in, the result must not be saved back;
+    /// use `.load_main_as_mut()` or `.load_owner_as_mut()` for that.
     pub async fn load_as_mut(&self) -> Result<DocumentMut> {
-        if self.is_loadable() {
-            let data = fs::read_to_string(&self.path).await?;
-            let _: LoadedConfig =
-                toml::from_str(&data).context("Failed to parse config data from valid TOML.")?;
+        Ok(self.load_resolved().await?.0)
+    }
+
+    /// Same as `.load_as_mut()`, also returning the name of the selected profile.
+    async fn load_resolved(&self) -> Result<(DocumentMut, Option<String>)> {
+        let data = self.read_main().await?;
+        let doc = data.parse::<DocumentMut>()?;
+
+        let includes = resolve_includes(&self.path, &doc)?;
+        let mut doc = merge_includes(&self.path, &data, doc, &includes)?;
+        let version = config_version(&doc)?;
+        migrate(&mut doc, version)?;
+        let profile = apply_profile(&mut doc)?;
+        resolve_brew_items(&mut doc)?;
+
+        let _: LoadedConfig = toml::from_str(&doc.to_string())
+            .map_err(|e| anyhow!("Failed to parse config data: {}", e.message()))?;
 
-            let doc = data.parse::<DocumentMut>()?;
+        Ok((doc, profile))
+    }
+
+    /// Loads only the main config file as mutable `DocumentMut`. Useful for in-place editing of values.
+    pub async fn load_main_as_mut(&self) -> Result<DocumentMut> {
+        let data = self.read_main().await?;
+        let doc = data.parse::<DocumentMut>()?;
 
-            Ok(doc)
+        // older layouts and conditional [brew] items are only valid once resolved
+        let mut resolved = doc.clone();
+        let version = config_version(&resolved)?;
+        migrate(&mut resolved, version)?;
+        resolve_brew_items(&mut resolved)?;
+        let _: LoadedConfig = toml::from_str(&resolved.to_string())
+            .map_err(|e| anyhow!("Failed to parse config data: {}", e.message()))?;
+
+        Ok(doc)
+    }
+
+    /// Loads the first config file (the main one, then included ones) for which `owns` returns true,
+    /// falling back to the main one. Returns its path alongside, for saving edits back in place.
+    pub async fn load_owner_as_mut(
+        &self,
+        mut owns: impl FnMut(&mut DocumentMut) -> bool,
+    ) -> Result<(PathBuf, DocumentMut)> {
+        let mut main = self.load_main_as_mut().await?;
+        if owns(&mut main) {
+            return Ok((self.path.clone(), main));
+        }
+
+        for path in resolve_includes(&self.path, &main)? {
+            let mut doc = fs::read_to_string(&path).await?.parse::<DocumentMut>()?;
+            if owns(&mut doc) {
+                return Ok((path, doc));
+            }
+        }
+
+        Ok((self.path.clone(), main))
+    }
+
+    /// Returns the paths of the main config file and all files included by it.
+    pub async fn sources(&self) -> Result<Vec<PathBuf>> {
+        let doc = self.read_main().await?.parse::<DocumentMut>()?;
+
+        let mut sources = vec![self.path.clone()];
+        sources.extend(resolve_includes(&self.path, &doc)?);
+
+        Ok(sources)
+    }
+
+    /// SHA256 digest of the config, covering all included files.
+    pub async fn digest(&self) -> Result<String> {
+        get_combined_digest(&self.sources().await?)
+    }
+
+    /// SHA256 digest of the config as it resolves on this machine: all of its files,
+    /// along with the selected profile and the values of the variables.
+    pub async fn resolved_digest(&self) -> Result<String> {
+        let (doc, profile) = self.load_resolved().await?;
+
+        let mut vars: Vec<(String, String)> = doc_vars(&doc)?.into_iter().collect();
+        vars.sort();
+
+        let mut parts = vec![self.digest().await?, profile.unwrap_or_default()];
+        for (name, value) in vars {
+            parts.push(name);
+            parts.push(value);
+        }
+
+        Ok(get_parts_digest(&parts))
+    }
+
+    async fn read_main(&self) -> Result<String> {
+        if self.is_loadable() {
+            Ok(fs::read_to_string(&self.path).await?)
         } else {
             bail!("Config path does not exist!")
         }
@@ -0,0 +1,183 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use toml_edit::{Document, DocumentMut, Item, Key, Table};
+
+/// Top-level key listing other config files to merge into the main one, relative to it.
+pub const INCLUDE_KEY: &str = "include";
+
+/// Reads the `include` patterns of a config document.
+fn include_patterns(doc: &DocumentMut) -> Result<Vec<String>> {
+    let Some(item) = doc.get(INCLUDE_KEY) else {
+        return Ok(Vec::new());
+    };
+
+    let Some(arr) = item.as_array() else {
+        bail!("`{INCLUDE_KEY}` must be a list of file paths.")
+    };
+
+    arr.iter()
+        .map(|p| match p.as_str() {
+            Some(p) => Ok(p.to_string()),
+            None => bail!("`{INCLUDE_KEY}` must be a list of file paths."),
+        })
+        .collect()
+}
+
+/// Resolves the files included by the main config, in the order they are listed.
+///
+/// Patterns are relative to the main config's directory and may use `*` and `?` in the file name
+/// (e.g. `work/*.toml`). Paths without wildcards must exist.
+pub fn resolve_includes(main: &Path, doc: &DocumentMut) -> Result<Vec<PathBuf>> {
+    let base = main.parent().unwrap_or(Path::new(""));
+    let mut paths = Vec::new();
+
+    for pattern in include_patterns(doc)? {
+        let path = base.join(&pattern);
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let matched = if name.contains(['*', '?']) {
+            let dir = path.parent().unwrap_or(base);
+            let mut matched: Vec<PathBuf> = fs::read_dir(dir)
+                .with_context(|| format!("Failed to read included directory {dir:?}"))?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| {
+                    p.is_file()
+                        && p.file_name()
+                            .is_some_and(|n| wildcard_match(&name, &n.to_string_lossy()))
+                })
+                .collect();
+            matched.sort();
+            matched
+        } else if path.is_file() {
+            vec![path]
+        } else {
+            bail!("Included file {pattern} does not exist.")
+        };
+
+        for path in matched {
+            if path != main && !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Merges the included files into the main config document.
+///
+/// Tables are merged recursively, while a key defined in more than one file is an error
+/// which points to both definitions.
+pub fn merge_includes(
+    main: &Path,
+    main_raw: &str,
+    mut doc: DocumentMut,
+    includes: &[PathBuf],
+) -> Result<DocumentMut> {
+    let mut sources = vec![(main.to_path_buf(), main_raw.to_string())];
+
+    for path in includes {
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read included file {path:?}"))?;
+        let included = raw
+            .parse::<DocumentMut>()
+            .with_context(|| format!("Failed to parse included file {path:?}"))?;
+
+        if included.contains_key(INCLUDE_KEY) {
+            bail!("Included file {path:?} cannot include other files.")
+        }
+
+        let mut key_path = Vec::new();
+        if let Err(conflict) = merge_table(doc.as_table_mut(), included.as_table(), &mut key_path) {
+            let first = sources
+                .iter()
+                .find_map(|(p, raw)| {
+                    line_of(raw, &conflict).map(|line| format!("{}:{line}", p.display()))
+                })
+                .unwrap_or_else(|| "another file".to_string());
+            let second = line_of(&raw, &conflict).map_or_else(
+                || path.display().to_string(),
+                |line| format!("{}:{line}", path.display()),
+            );
+
+            bail!(
+                "Conflicting definitions of {} in {first} and {second}.",
+                conflict.join(".")
+            )
+        }
+
+        sources.push((path.clone(), raw));
+    }
+
+    Ok(doc)
+}
+
+/// Recursively merges `from` into `into`. Returns the path of the first key defined in both.
+fn merge_table(into: &mut Table, from: &Table, path: &mut Vec<String>) -> Result<(), Vec<String>> {
+    for (key, item) in from {
+        path.push(key.to_string());
+
+        match (into.get_mut(key), item) {
+            (Some(Item::Table(existing)), Item::Table(t)) => merge_table(existing, t, path)?,
+            (Some(_), _) => return Err(path.clone()),
+            (None, _) => {
+                into.insert(key, item.clone());
+            }
+        }
+
+        path.pop();
+    }
+
+    Ok(())
+}
+
+/// Finds the line a (dotted) key is defined on, if the source has it.
+fn line_of(raw: &str, path: &[String]) -> Option<usize> {
+    let doc = Document::parse(raw).ok()?;
+    let (last, parents) = path.split_last()?;
+
+    let mut table = doc.as_table();
+    for key in parents {
+        table = table.get(key)?.as_table()?;
+    }
+
+    let span = table
+        .key(last)
+        .and_then(Key::span)
+        .or_else(|| table.get(last)?.span())?;
+
+    Some(raw[..span.start].matches('\n').count() + 1)
+}
+
+/// Matches a file name against a pattern with `*` (any run of characters) and `?` (one character).
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let (p, n): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    let (mut pi, mut ni) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((sp, sn)) = star {
+            // let the last star swallow one more character
+            pi = sp + 1;
+            ni = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+
+    p[pi..].iter().all(|c| *c == '*')
+}
@@ -0,0 +1,205 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+use tokio::fs;
+use toml_edit::{DocumentMut, Item, TableLike, value};
+
+use crate::{
+    cli::atomic::should_dry_run,
+    config::{Config, ConfigCoreMethods, include::resolve_includes},
+    log_dry, log_err, log_warn,
+    util::io::confirm,
+};
+
+/// Top-level key holding the layout version of the config.
+pub const VERSION_KEY: &str = "version";
+
+/// The config layout version this build of cutler reads.
+pub const CONFIG_VERSION: i64 = 1;
+
+/// A single step of the migration pipeline, upgrading a config to version `to`.
+struct Migration {
+    to: i64,
+    /// Rewrites the document in place, returning a description of every change made.
+    run: fn(&mut DocumentMut) -> Result<Vec<String>>,
+}
+
+const MIGRATIONS: &[Migration] = &[Migration { to: 1, run: to_v1 }];
+
+/// Returns the layout version of a config. Configs without a `version` key predate versioning.
+pub fn config_version(doc: &DocumentMut) -> Result<i64> {
+    let Some(item) = doc.get(VERSION_KEY) else {
+        return Ok(0);
+    };
+
+    match item.as_integer() {
+        Some(v) if v > CONFIG_VERSION => bail!(
+            "Config version {v} is newer than this cutler supports ({CONFIG_VERSION}). Run `cutler self-update` first."
+        ),
+        Some(v) if v >= 0 => Ok(v),
+        _ => bail!("`{VERSION_KEY}` must be a non-negative integer."),
+    }
+}
+
+/// Runs every migration after version `from` on the document, returning the changes made.
+/// The `version` key itself is left alone.
+pub fn migrate(doc: &mut DocumentMut, from: i64) -> Result<Vec<String>> {
+    let mut changes = Vec::new();
+
+    for migration in MIGRATIONS.iter().filter(|m| m.to > from) {
+        changes.extend((migration.run)(doc)?);
+    }
+
+    Ok(changes)
+}
+
+/// Version 1 moved commands and their variables out of `[external]` and renamed `[commands]`.
+fn to_v1(doc: &mut DocumentMut) -> Result<Vec<String>> {
+    let mut changes = Vec::new();
+
+    for (from, to) in [
+        (&["external", "variables"][..], "vars"),
+        (&["external", "command"][..], "command"),
+        (&["commands"][..], "command"),
+    ] {
+        if move_table(doc, from, to)? {
+            changes.push(format!("Moved [{}] to [{to}].", from.join(".")));
+        }
+    }
+
+    // drop the now empty [external]
+    if doc
+        .get("external")
+        .and_then(Item::as_table_like)
+        .is_some_and(TableLike::is_empty)
+    {
+        doc.remove("external");
+    }
+
+    Ok(changes)
+}
+
+/// Moves the table at the dotted path `from` to the top-level table `to`, merging it into `to`
+/// if that already exists. Comments travel along with the moved tables.
+fn move_table(doc: &mut DocumentMut, from: &[&str], to: &str) -> Result<bool> {
+    let Some((last, parents)) = from.split_last() else {
+        return Ok(false);
+    };
+    let name = from.join(".");
+
+    let mut parent: &mut dyn TableLike = doc.as_table_mut();
+    for key in parents {
+        match parent.get_mut(key).and_then(Item::as_table_like_mut) {
+            Some(t) => parent = t,
+            None => return Ok(false),
+        }
+    }
+    let Some(item) = parent.remove(last) else {
+        return Ok(false);
+    };
+
+    let Some(moved) = item.as_table_like() else {
+        bail!("Cannot migrate [{name}]: expected a table.")
+    };
+
+    match doc.get_mut(to) {
+        Some(existing) => {
+            let Some(target) = existing.as_table_like_mut() else {
+                bail!("Cannot migrate [{name}]: `{to}` is not a table.")
+            };
+
+            for (key, item) in moved.iter() {
+                if target.contains_key(key) {
+                    bail!("Cannot migrate [{name}]: [{to}] already defines `{key}`.")
+                }
+                target.insert(key, item.clone());
+            }
+        }
+        None => {
+            doc.insert(to, item);
+        }
+    }
+
+    Ok(true)
+}
+
+/// A config file along with its migrated contents.
+pub struct MigratedFile {
+    pub path: PathBuf,
+    pub doc: DocumentMut,
+    /// Descriptions of the layout changes, apart from the `version` update.
+    pub changes: Vec<String>,
+}
+
+/// Migrates the main config and the files it includes to the current layout, without saving.
+/// Only files which change are returned; the main one also whenever its `version` is outdated.
+pub async fn migrate_files(config: &Config) -> Result<Vec<MigratedFile>> {
+    let mut main = config.load_main_as_mut().await?;
+    let version = config_version(&main)?;
+
+    let mut files = Vec::new();
+
+    for path in resolve_includes(config.path(), &main)? {
+        let mut doc = fs::read_to_string(&path).await?.parse::<DocumentMut>()?;
+        let changes = migrate(&mut doc, version)?;
+
+        if !changes.is_empty() {
+            files.push(MigratedFile { path, doc, changes });
+        }
+    }
+
+    let changes = migrate(&mut main, version)?;
+    if version != CONFIG_VERSION {
+        main.insert(VERSION_KEY, value(CONFIG_VERSION));
+        files.insert(
+            0,
+            MigratedFile {
+                path: config.path().to_path_buf(),
+                doc: main,
+                changes,
+            },
+        );
+    }
+
+    Ok(files)
+}
+
+/// Offers to migrate a config written in an older layout. This should be called early in `main()`.
+///
+/// If declined, the config is still migrated in memory whenever it's loaded.
+pub async fn try_auto_migrate(config: &Config) {
+    if !config.is_loadable() || config.is_locked().await {
+        return;
+    }
+
+    // loading the config reports any error as well
+    let Ok(files) = migrate_files(config).await else {
+        return;
+    };
+
+    // a missing version alone isn't worth a prompt
+    if files.iter().all(|f| f.changes.is_empty()) {
+        return;
+    }
+
+    log_warn!("The config uses an older layout:");
+    for change in files.iter().flat_map(|f| &f.changes) {
+        log_warn!("  {change}");
+    }
+
+    if should_dry_run() {
+        log_dry!("Would offer to migrate the config to version {CONFIG_VERSION}.");
+        return;
+    }
+
+    if !confirm("Migrate the config files now?") {
+        log_warn!("Run `cutler config migrate` to migrate the config later.");
+        return;
+    }
+
+    for file in files {
+        if let Err(e) = file.doc.save(&file.path).await {
+            log_err!("Failed to save migrated config {:?}: {e}", file.path);
+        }
+    }
+}
@@ -1,6 +1,12 @@
 mod core;
+pub mod include;
+pub mod migrate;
 mod path;
+pub mod profile;
 pub mod remote;
+pub mod secrets;
+pub mod vars;
+pub mod when;
 
 pub use core::*;
 pub use path::get_config_path;
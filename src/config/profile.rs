@@ -0,0 +1,144 @@
+use std::sync::{Once, OnceLock};
+
+use anyhow::{Result, bail};
+use toml_edit::{DocumentMut, Item, Table, Value};
+
+use crate::log_info;
+
+/// Top-level table holding the profiles, e.g. `[profile.work]`.
+pub const PROFILE_KEY: &str = "profile";
+
+/// Environment variable selecting a profile, if `--profile` isn't passed.
+pub const PROFILE_ENV: &str = "CUTLER_PROFILE";
+
+/// The tables which a profile overlays onto the base config.
+const OVERLAY_KEYS: [&str; 4] = ["set", "brew", "command", "vars"];
+
+/// The profile passed with `--profile` for the current process.
+static PROFILE: OnceLock<String> = OnceLock::new();
+
+/// Only announce the profile once per run, even though the config is loaded several times.
+static ANNOUNCE: Once = Once::new();
+
+/// Sets the profile passed through `--profile`.
+pub fn set_profile(name: String) {
+    PROFILE.set(name).ok();
+}
+
+/// Returns the hostname of this machine, if it can be determined.
+#[must_use]
+pub fn hostname() -> Option<String> {
+    nix::unistd::gethostname().ok()?.into_string().ok()
+}
+
+/// Whether `pattern` names this host, ignoring case and any domain suffix (e.g. `.local`).
+#[must_use]
+pub fn hostname_matches(pattern: &str, host: &str) -> bool {
+    let short = |h: &str| h.split('.').next().unwrap_or(h).to_lowercase();
+
+    pattern.eq_ignore_ascii_case(host) || short(pattern) == short(host)
+}
+
+/// Decides the profile to use: `--profile` first, then `CUTLER_PROFILE`, then the first profile
+/// whose `hostnames` list this machine.
+fn select_profile(profiles: &Table) -> Result<Option<String>> {
+    let explicit = PROFILE
+        .get()
+        .cloned()
+        .or_else(|| std::env::var(PROFILE_ENV).ok().filter(|p| !p.is_empty()));
+
+    if let Some(name) = explicit {
+        if !profiles.contains_key(&name) {
+            let available: Vec<&str> = profiles.iter().map(|(k, _)| k).collect();
+            bail!(
+                "Profile {name} does not exist. Available profiles: {}",
+                if available.is_empty() {
+                    "none".to_string()
+                } else {
+                    available.join(", ")
+                }
+            )
+        }
+        return Ok(Some(name));
+    }
+
+    let Some(host) = hostname() else {
+        return Ok(None);
+    };
+
+    let matched = profiles.iter().find(|(_, profile)| {
+        profile
+            .get("hostnames")
+            .and_then(Item::as_array)
+            .is_some_and(|hosts| {
+                hosts
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .any(|h| hostname_matches(h, &host))
+            })
+    });
+
+    Ok(matched.map(|(name, _)| name.to_string()))
+}
+
+/// Overlays the selected profile (if any) onto the base config. Returns the profile's name.
+///
+/// Tables are merged key by key, with the profile's values taking precedence,
+/// except for `[brew]` lists which are extended instead of replaced.
+pub fn apply_profile(doc: &mut DocumentMut) -> Result<Option<String>> {
+    let Some(profiles) = doc.get(PROFILE_KEY).and_then(Item::as_table) else {
+        return Ok(None);
+    };
+    let Some(name) = select_profile(profiles)? else {
+        return Ok(None);
+    };
+    let Some(profile) = profiles.get(&name).and_then(Item::as_table).cloned() else {
+        bail!("Profile {name} must be a table.")
+    };
+
+    for key in OVERLAY_KEYS {
+        let Some(item) = profile.get(key) else {
+            continue;
+        };
+
+        match (doc.get_mut(key), item) {
+            (Some(Item::Table(base)), Item::Table(overlay)) => {
+                overlay_table(base, overlay, key == "brew");
+            }
+            _ => {
+                doc.insert(key, item.clone());
+            }
+        }
+    }
+
+    ANNOUNCE.call_once(|| log_info!("Using profile {name}."));
+
+    Ok(Some(name))
+}
+
+/// Recursively overlays `from` onto `into`. Arrays are extended if `extend_arrays` is set.
+fn overlay_table(into: &mut Table, from: &Table, extend_arrays: bool) {
+    for (key, item) in from {
+        match (into.get_mut(key), item) {
+            (Some(Item::Table(base)), Item::Table(overlay)) => {
+                overlay_table(base, overlay, extend_arrays);
+            }
+            (Some(Item::Value(Value::Array(base))), Item::Value(Value::Array(extra)))
+                if extend_arrays =>
+            {
+                for value in extra {
+                    let duplicate = base.iter().any(
+                        |v| matches!((v.as_str(), value.as_str()), (Some(a), Some(b)) if a == b),
+                    );
+
+                    if !duplicate {
+                        base.push(value.clone());
+                    }
+                }
+            }
+            _ => {
+                into.insert(key, item.clone());
+            }
+        }
+    }
+}
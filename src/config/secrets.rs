@@ -0,0 +1,80 @@
+use std::{env, fs, path::PathBuf, process::Command};
+
+use anyhow::{Context, Result, bail};
+
+/// Environment variable which, when set, points cutler to a directory of secret files
+/// to use instead of the macOS keychain.
+pub const SECRETS_DIR_ENV: &str = "CUTLER_SECRETS_DIR";
+
+/// Trait for implementing a store which secret variables (`{ keychain = "..." }`) are read from.
+pub trait SecretProvider: Send + Sync {
+    /// Short, human-readable name of the provider.
+    fn name(&self) -> &'static str;
+
+    /// Reads the secret stored under the given service name.
+    fn get(&self, service: &str) -> Result<String>;
+}
+
+/// Reads generic passwords from the login keychain through `security`.
+pub struct KeychainProvider;
+
+impl SecretProvider for KeychainProvider {
+    fn name(&self) -> &'static str {
+        "keychain"
+    }
+
+    fn get(&self, service: &str) -> Result<String> {
+        let output = Command::new("security")
+            .args(["find-generic-password", "-s", service, "-w"])
+            .output()
+            .context("could not run `security`")?;
+
+        if !output.status.success() {
+            bail!("no keychain item found for {service}")
+        }
+
+        Ok(String::from_utf8(output.stdout)?.trim_end().to_string())
+    }
+}
+
+/// Reads secrets from a directory holding one file per service, e.g. `<dir>/github-token`.
+pub struct FileSecretProvider {
+    root: PathBuf,
+}
+
+impl FileSecretProvider {
+    #[must_use]
+    pub const fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+}
+
+impl SecretProvider for FileSecretProvider {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn get(&self, service: &str) -> Result<String> {
+        if service.contains('/') || service.starts_with('.') {
+            bail!("invalid secret name {service}")
+        }
+
+        let path = self.root.join(service);
+        Ok(fs::read_to_string(&path)
+            .with_context(|| format!("could not read {path:?}"))?
+            .trim_end()
+            .to_string())
+    }
+}
+
+/// Decides the secret provider for the current process.
+///
+/// If `CUTLER_SECRETS_DIR` is set, secrets are read from files in that directory.
+/// Otherwise, the macOS keychain is used.
+#[must_use]
+pub fn get_secret_provider() -> Box<dyn SecretProvider> {
+    match env::var(SECRETS_DIR_ENV) {
+        Ok(dir) if !dir.is_empty() => Box::new(FileSecretProvider::new(PathBuf::from(dir))),
+        _ => Box::new(KeychainProvider),
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::IsTerminal,
+    process::Command,
+    sync::{LazyLock, Mutex, OnceLock, PoisonError},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use dialoguer::{Input, Password};
+use serde::Deserialize;
+use toml::Value;
+use toml_edit::{DocumentMut, Item};
+
+use crate::{
+    cli::atomic::should_accept_all, config::secrets::get_secret_provider,
+    domains::convert::toml_edit_to_toml, util::logging::register_secret,
+};
+
+/// Top-level table holding the variables.
+pub const VARS_KEY: &str = "vars";
+
+/// A variable in `[vars]`, e.g. `user = { env = "USER", default = "me" }`.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "Value")]
+pub struct Var {
+    pub source: VarSource,
+    /// The value to fall back to if the source yields nothing.
+    pub default: Option<String>,
+    /// Whether the value is masked in all output.
+    pub secret: bool,
+}
+
+/// Where the value of a variable comes from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VarSource {
+    /// A plain string.
+    Literal(String),
+    /// The output of a shell command, without the trailing newline.
+    Cmd(String),
+    /// The contents of a file (`~/` is expanded).
+    File(String),
+    /// An environment variable.
+    Env(String),
+    /// An answer to a question asked on the terminal.
+    Prompt(String),
+    /// A secret stored under a service name in the keychain (or its stand-in).
+    Keychain(String),
+}
+
+impl TryFrom<Value> for Var {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let table = match value {
+            Value::String(s) => {
+                return Ok(Self {
+                    source: VarSource::Literal(s),
+                    default: None,
+                    secret: false,
+                });
+            }
+            Value::Table(t) => t,
+            _ => return Err("expected a string or a table like { cmd = \"...\" }".to_string()),
+        };
+
+        let mut sources = Vec::new();
+        let mut default = None;
+        let mut secret = false;
+
+        for (key, value) in table {
+            if key == "secret" {
+                secret = value.as_bool().ok_or("`secret` must be a boolean")?;
+                continue;
+            }
+            let Value::String(value) = value else {
+                return Err(format!("`{key}` must be a string"));
+            };
+
+            match key.as_str() {
+                "cmd" => sources.push(VarSource::Cmd(value)),
+                "file" => sources.push(VarSource::File(value)),
+                "env" => sources.push(VarSource::Env(value)),
+                "prompt" => sources.push(VarSource::Prompt(value)),
+                "keychain" => sources.push(VarSource::Keychain(value)),
+                "default" => default = Some(value),
+                other => {
+                    return Err(format!(
+                        "unknown field `{other}`, expected one of `cmd`, `file`, `env`, `prompt`, `keychain`, `default`, `secret`"
+                    ));
+                }
+            }
+        }
+
+        match <[VarSource; 1]>::try_from(sources) {
+            Ok([source]) => Ok(Self {
+                source,
+                default,
+                secret,
+            }),
+            Err(_) => Err(
+                "expected exactly one of `cmd`, `file`, `env`, `prompt` or `keychain`".to_string(),
+            ),
+        }
+    }
+}
+
+/// Values passed through `--var key=value`, which take precedence over `[vars]`.
+static OVERRIDES: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Variables resolved so far in this run, along with the definition they came from.
+/// A variable is only resolved again if its definition changes (e.g. while watching the config).
+static RESOLVED: LazyLock<Mutex<HashMap<String, (Var, String)>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Sets the variables passed through `--var`.
+pub fn set_var_overrides(vars: Vec<(String, String)>) {
+    OVERRIDES.set(vars.into_iter().collect()).ok();
+}
+
+/// Parses a `--var key=value` argument.
+pub fn parse_var_override(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got `{arg}`")),
+    }
+}
+
+impl Var {
+    /// Reads the value of the variable from its source.
+    fn resolve(&self, name: &str) -> Result<String> {
+        let value = match &self.source {
+            VarSource::Literal(s) => return Ok(s.clone()),
+            VarSource::Cmd(cmd) => run_cmd(cmd),
+            VarSource::File(path) => read_file(path),
+            VarSource::Env(key) => {
+                env::var(key).with_context(|| format!("environment variable {key} is not set"))
+            }
+            VarSource::Prompt(question) => ask(question, self.default.as_deref(), self.secret),
+            VarSource::Keychain(service) => {
+                let provider = get_secret_provider();
+                provider
+                    .get(service)
+                    .with_context(|| format!("{} provider failed", provider.name()))
+            }
+        };
+
+        match (value, &self.default) {
+            (Ok(value), _) => Ok(value),
+            (Err(_), Some(default)) => Ok(default.clone()),
+            (Err(e), None) => Err(anyhow!("Failed to resolve variable {name}: {e}")),
+        }
+    }
+}
+
+fn run_cmd(cmd: &str) -> Result<String> {
+    let output = Command::new("sh").args(["-c", cmd]).output()?;
+
+    if !output.status.success() {
+        bail!("`{cmd}` exited with {}", output.status)
+    }
+
+    Ok(String::from_utf8(output.stdout)?.trim_end().to_string())
+}
+
+fn read_file(path: &str) -> Result<String> {
+    let path = match path.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir()
+            .context("could not determine home directory")?
+            .join(rest),
+        None => path.into(),
+    };
+
+    Ok(fs::read_to_string(&path)
+        .with_context(|| format!("could not read {path:?}"))?
+        .trim_end()
+        .to_string())
+}
+
+/// Asks for a value on the terminal, hiding the input of secrets. Without a terminal
+/// (or with `--accept-all`), fails so that the default is used instead.
+fn ask(question: &str, default: Option<&str>, secret: bool) -> Result<String> {
+    if should_accept_all() || !std::io::stdin().is_terminal() {
+        bail!("cannot prompt for a value; pass it with --var")
+    }
+
+    if secret {
+        let value = Password::new()
+            .with_prompt(question)
+            .allow_empty_password(default.is_some())
+            .interact()?;
+
+        return match (value.is_empty(), default) {
+            (true, Some(default)) => Ok(default.to_string()),
+            _ => Ok(value),
+        };
+    }
+
+    let mut input = Input::<String>::new().with_prompt(question);
+    if let Some(default) = default {
+        input = input.default(default.to_string());
+    }
+
+    Ok(input.interact_text()?)
+}
+
+/// Resolves variables into their values, along with the `--var` overrides.
+///
+/// Each variable is resolved once per run, so commands and prompts aren't repeated
+/// every time the config is read.
+pub fn resolve_vars(vars: Option<&HashMap<String, Var>>) -> Result<HashMap<String, String>> {
+    let overrides = OVERRIDES.get();
+    let mut out = overrides.cloned().unwrap_or_default();
+
+    let mut names: Vec<&String> = vars.into_iter().flat_map(HashMap::keys).collect();
+    names.sort();
+
+    let mut resolved = RESOLVED.lock().unwrap_or_else(PoisonError::into_inner);
+
+    for name in names {
+        let Some(var) = vars.and_then(|v| v.get(name)) else {
+            continue;
+        };
+        if let Some(value) = out.get(name) {
+            if var.secret {
+                register_secret(value);
+            }
+            continue;
+        }
+
+        let value = match resolved.get(name) {
+            Some((cached, value)) if cached == var => value.clone(),
+            _ => {
+                let value = var.resolve(name)?;
+                if var.secret {
+                    register_secret(&value);
+                }
+                resolved.insert(name.clone(), (var.clone(), value.clone()));
+                value
+            }
+        };
+        out.insert(name.clone(), value);
+    }
+
+    Ok(out)
+}
+
+/// Reads and resolves the `[vars]` of a config document.
+pub fn doc_vars(doc: &DocumentMut) -> Result<HashMap<String, String>> {
+    let vars: Option<HashMap<String, Var>> = match doc.get(VARS_KEY) {
+        None => None,
+        Some(Item::Table(t)) => Some(toml::from_str(&t.to_string())?),
+        Some(Item::Value(v)) => Some(toml_edit_to_toml(v)?.try_into()?),
+        Some(_) => bail!("`{VARS_KEY}` must be a table."),
+    };
+
+    resolve_vars(vars.as_ref())
+}
@@ -0,0 +1,142 @@
+use std::{process::Command, sync::LazyLock};
+
+use anyhow::{Result, anyhow, bail};
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use toml_edit::{DocumentMut, Item, Value};
+
+use crate::{config::profile::hostname_matches, domains::convert::toml_edit_to_toml};
+
+/// Key of a condition clause, e.g. `when = { arch = "arm64" }`.
+pub const WHEN_KEY: &str = "when";
+
+/// A condition on the machine, all parts of which must hold.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct When {
+    /// The machine's hostname, ignoring case and any domain suffix.
+    pub hostname: Option<String>,
+    /// A semver requirement on the macOS version, e.g. `>=14`.
+    pub macos: Option<VersionReq>,
+    /// The CPU architecture, e.g. `arm64` or `x86_64`.
+    pub arch: Option<String>,
+}
+
+/// Facts about the current machine which conditions are checked against.
+struct Facts {
+    hostname: Option<String>,
+    macos: Option<Version>,
+    arch: &'static str,
+}
+
+static FACTS: LazyLock<Facts> = LazyLock::new(|| Facts {
+    hostname: crate::config::profile::hostname(),
+    macos: macos_version(),
+    arch: normalize_arch(std::env::consts::ARCH),
+});
+
+/// Reads the macOS version through `sw_vers`, padding it to a full semver version (`14.2` → `14.2.0`).
+fn macos_version() -> Option<Version> {
+    let output = Command::new("sw_vers")
+        .arg("-productVersion")
+        .output()
+        .ok()?;
+    let raw = String::from_utf8(output.stdout).ok()?;
+
+    let mut parts: Vec<&str> = raw.trim().split('.').collect();
+    parts.resize(3, "0");
+
+    Version::parse(&parts.join(".")).ok()
+}
+
+/// Maps the different names of an architecture onto Rust's.
+fn normalize_arch(arch: &str) -> &str {
+    match arch {
+        "arm64" | "aarch64" | "apple" => "aarch64",
+        "x86_64" | "amd64" | "x64" | "intel" => "x86_64",
+        other => other,
+    }
+}
+
+impl When {
+    /// Parses a condition written as a table in the config.
+    pub fn from_item(item: &Item) -> Result<Self> {
+        let value = match item {
+            Item::Value(v) => toml_edit_to_toml(v)?,
+            Item::Table(t) => toml::from_str::<toml::Value>(&t.to_string())?,
+            _ => return Err(anyhow!("`{WHEN_KEY}` must be a table.")),
+        };
+
+        value
+            .try_into()
+            .map_err(|e| anyhow!("Invalid `{WHEN_KEY}` clause: {}", e.message()))
+    }
+
+    /// Whether the condition holds on this machine.
+    /// Conditions on facts which cannot be determined (e.g. the macOS version elsewhere) never hold.
+    #[must_use]
+    pub fn applies(&self) -> bool {
+        let facts = &*FACTS;
+
+        let hostname = self.hostname.as_ref().is_none_or(|want| {
+            facts
+                .hostname
+                .as_ref()
+                .is_some_and(|host| hostname_matches(want, host))
+        });
+        let macos = self
+            .macos
+            .as_ref()
+            .is_none_or(|req| facts.macos.as_ref().is_some_and(|v| req.matches(v)));
+        let arch = self
+            .arch
+            .as_ref()
+            .is_none_or(|want| normalize_arch(&want.to_lowercase()) == facts.arch);
+
+        hostname && macos && arch
+    }
+}
+
+/// Resolves conditional items in the `[brew]` lists, e.g. `{ name = "mas", when = { ... } }`,
+/// into their names, dropping the ones whose condition doesn't hold.
+pub fn resolve_brew_items(doc: &mut DocumentMut) -> Result<()> {
+    let Some(brew) = doc.get_mut("brew").and_then(Item::as_table_like_mut) else {
+        return Ok(());
+    };
+
+    for list in ["formulae", "casks", "taps"] {
+        let Some(items) = brew.get_mut(list).and_then(Item::as_array_mut) else {
+            continue;
+        };
+
+        let mut resolved = Vec::new();
+        for item in items.iter() {
+            match item {
+                Value::InlineTable(tbl) => {
+                    let (Some(name), true) = (
+                        tbl.get("name").and_then(Value::as_str),
+                        tbl.iter().all(|(k, _)| k == "name" || k == WHEN_KEY),
+                    ) else {
+                        bail!(
+                            "Conditional items in [brew] {list} must look like: {{ name = \"...\", when = {{ ... }} }}"
+                        )
+                    };
+                    let when = match tbl.get(WHEN_KEY) {
+                        Some(w) => When::from_item(&Item::Value(w.clone()))?,
+                        None => When::default(),
+                    };
+
+                    if when.applies() {
+                        resolved.push(Value::from(name));
+                    }
+                }
+                other => resolved.push(other.clone()),
+            }
+        }
+
+        items.clear();
+        items.extend(resolved);
+    }
+
+    Ok(())
+}
@@ -1,13 +1,40 @@
 use crate::{
+    backend::{PreferencesBackend, get_prefs_backend},
     config::{Config, get_config_path},
     snapshot::{Snapshot, get_snapshot_path},
 };
 use anyhow::{Context, Result};
+use std::sync::OnceLock;
 use tokio::fs;
 
 pub struct AppContext {
     pub config: Config,
     pub snapshot: Snapshot,
+    prefs: OnceLock<Box<dyn PreferencesBackend>>,
+}
+
+impl AppContext {
+    #[must_use]
+    pub const fn new(config: Config, snapshot: Snapshot) -> Self {
+        Self {
+            config,
+            snapshot,
+            prefs: OnceLock::new(),
+        }
+    }
+
+    /// Returns the preferences backend, deciding it on first use.
+    ///
+    /// This is done lazily so that commands which never touch preferences
+    /// (e.g. `init`, `config` or `brew`) work on any platform.
+    pub fn prefs(&self) -> Result<&dyn PreferencesBackend> {
+        if let Some(prefs) = self.prefs.get() {
+            return Ok(prefs.as_ref());
+        }
+
+        let prefs = get_prefs_backend()?;
+        Ok(self.prefs.get_or_init(|| prefs).as_ref())
+    }
 }
 
 pub struct AppContextManager;
@@ -43,6 +70,6 @@ impl AppContextManager {
         let snapshot_path = get_snapshot_path()?;
         let snapshot = Snapshot::new(snapshot_path);
 
-        Ok(AppContext { config, snapshot })
+        Ok(AppContext::new(config, snapshot))
     }
 }
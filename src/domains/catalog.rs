@@ -0,0 +1,147 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use anyhow::{Result, anyhow};
+use defaults_rs::PrefValue;
+use serde::Deserialize;
+use toml::Value;
+
+use crate::domains::convert::toml_to_prefvalue;
+
+/// A well-known preference key.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CatalogEntry {
+    /// The plist type name, as returned by `PrefValue::get_type`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub description: String,
+    /// The only values which the key accepts, if limited.
+    pub values: Option<Vec<Value>>,
+    /// Inclusive bounds for numeric keys.
+    pub range: Option<[f64; 2]>,
+    /// Processes to restart for changes to take effect.
+    #[serde(default)]
+    pub restart: Vec<String>,
+}
+
+/// The embedded catalog of well-known domains and keys.
+#[derive(Deserialize, Debug)]
+#[serde(transparent)]
+pub struct Catalog {
+    domains: HashMap<String, HashMap<String, CatalogEntry>>,
+}
+
+static CATALOG: LazyLock<Result<Catalog, String>> =
+    LazyLock::new(|| toml::from_str(include_str!("catalog.toml")).map_err(|e| e.to_string()));
+
+impl Catalog {
+    /// Returns the embedded catalog.
+    pub fn get() -> Result<&'static Self> {
+        CATALOG
+            .as_ref()
+            .map_err(|e| anyhow!("Built-in preference catalog is invalid: {e}"))
+    }
+
+    /// Returns the known keys of an effective domain, if the domain is catalogued.
+    #[must_use]
+    pub fn domain(&self, eff_domain: &str) -> Option<&HashMap<String, CatalogEntry>> {
+        self.domains.get(eff_domain)
+    }
+
+    /// All catalogued domain names.
+    pub fn domain_names(&self) -> impl Iterator<Item = &str> {
+        self.domains.keys().map(String::as_str)
+    }
+
+    /// Processes to restart after writing to an effective domain, from all of its catalogued keys.
+    #[must_use]
+    pub fn restarts_for(&self, eff_domain: &str) -> Vec<&str> {
+        self.domain(eff_domain)
+            .into_iter()
+            .flat_map(HashMap::values)
+            .flat_map(|e| e.restart.iter().map(String::as_str))
+            .collect()
+    }
+}
+
+/// Largest edit distance at which a name still counts as a typo of a known one.
+const MAX_TYPO_DISTANCE: usize = 3;
+
+impl CatalogEntry {
+    /// Whether a value of the given plist type is accepted by the entry.
+    /// Integers are accepted for floats, since `autohide-delay = 0` means the same as `0.0`.
+    #[must_use]
+    pub fn accepts_type(&self, kind: &str) -> bool {
+        kind == self.kind || (self.kind == "float" && kind == "integer")
+    }
+
+    /// Checks a value against the entry. Returns a description of the problem, if any.
+    #[must_use]
+    pub fn check(&self, value: &PrefValue) -> Option<String> {
+        if !self.accepts_type(value.get_type()) {
+            return Some(format!(
+                "expected {}, found {}",
+                self.kind,
+                value.get_type()
+            ));
+        }
+
+        if let Some(values) = &self.values {
+            let allowed: Vec<PrefValue> = values
+                .iter()
+                .filter_map(|v| toml_to_prefvalue(v).ok())
+                .collect();
+
+            if !allowed.contains(value) {
+                let allowed: Vec<String> = allowed.iter().map(ToString::to_string).collect();
+                return Some(format!("expected one of: {}", allowed.join(", ")));
+            }
+        }
+
+        if let Some([min, max]) = self.range {
+            let number = match value {
+                PrefValue::Integer(i) => *i as f64,
+                PrefValue::Float(f) => *f,
+                _ => return None,
+            };
+
+            if number < min || number > max {
+                return Some(format!("expected a value between {min} and {max}"));
+            }
+        }
+
+        None
+    }
+}
+
+/// Returns the candidate closest to `word`, if it is close enough to be a likely typo.
+#[must_use]
+pub fn suggest<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let lower = word.to_lowercase();
+
+    candidates
+        .into_iter()
+        .map(|c| (c, levenshtein(&lower, &c.to_lowercase())))
+        .filter(|(c, dist)| *dist <= (c.len() / 3).clamp(2, MAX_TYPO_DISTANCE))
+        .min_by_key(|(_, dist)| *dist)
+        .map(|(c, _)| c)
+}
+
+/// Edit distance between two strings.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1];
+
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            curr.push((prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1));
+        }
+
+        prev = curr;
+    }
+
+    prev[b.len()]
+}
@@ -1,12 +1,20 @@
-use anyhow::{Result, bail};
+use anyhow::{Result, anyhow, bail};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
 use defaults_rs::PrefValue;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use toml::Value;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use toml::{Value, value::Datetime};
 use toml_edit::Value as EditValue;
 
 /// Serializable representation of a preference value.
 /// This mirrors the structure of `defaults_rs::PrefValue` but implements Serialize/Deserialize.
+///
+/// Dates and binary data are stored as `{"$date": <seconds since 2001>}` and `{"$data": "<base64>"}`.
+/// The `$` keeps them apart from real dictionaries with a `date` or `data` key, while
+/// listing them before `Dictionary` lets them take precedence when deserializing.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum SerializablePrefValue {
@@ -15,9 +23,80 @@ pub enum SerializablePrefValue {
     Float(f64),
     Boolean(bool),
     Array(Vec<SerializablePrefValue>),
+    Date(SerializableDate),
+    Data(SerializableData),
     Dictionary(HashMap<String, SerializablePrefValue>),
 }
 
+/// A plist date, in seconds since the Apple epoch (2001-01-01).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SerializableDate {
+    #[serde(rename = "$date")]
+    pub date: f64,
+}
+
+/// Plist binary data, stored as base64.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SerializableData {
+    #[serde(
+        rename = "$data",
+        serialize_with = "to_base64",
+        deserialize_with = "from_base64"
+    )]
+    pub data: Vec<u8>,
+}
+
+fn to_base64<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&BASE64.encode(bytes))
+}
+
+fn from_base64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let encoded = String::deserialize(deserializer)?;
+    BASE64.decode(encoded).map_err(serde::de::Error::custom)
+}
+
+/// Key of the inline table which carries binary data in the config, e.g. `{ data = "AAEC" }`.
+pub const DATA_KEY: &str = "data";
+
+/// Decodes a `{ data = "<base64>" }` table, if that's what the given table is.
+/// Such a table always stands for binary data, never for a dictionary.
+fn table_to_data(tbl: &toml::Table) -> Option<Result<Vec<u8>>> {
+    match (tbl.len(), tbl.get(DATA_KEY)) {
+        (1, Some(Value::String(encoded))) => Some(
+            BASE64
+                .decode(encoded)
+                .map_err(|e| anyhow!("Invalid base64 in data value: {e}")),
+        ),
+        _ => None,
+    }
+}
+
+/// Turns a TOML datetime into seconds since the Apple epoch.
+///
+/// Datetimes without an offset are taken as UTC, and bare dates as midnight UTC.
+fn datetime_to_apple_secs(dt: &Datetime) -> Result<f64> {
+    let rfc3339 = match (dt.date, dt.time, dt.offset) {
+        (Some(_), Some(_), Some(_)) => dt.to_string(),
+        (Some(_), Some(_), None) => format!("{dt}Z"),
+        (Some(_), None, _) => format!("{dt}T00:00:00Z"),
+        (None, ..) => bail!("Time-only value {dt} cannot be used as a date."),
+    };
+
+    let date = plist::Date::from_xml_format(&rfc3339).map_err(|_| anyhow!("Invalid date: {dt}"))?;
+
+    Ok(plist_date_to_apple_secs(date))
+}
+
+/// Turns seconds since the Apple epoch into a (UTC) TOML datetime.
+fn apple_secs_to_datetime(ts: f64) -> Result<Datetime> {
+    apple_secs_to_plist_date(ts)
+        .to_xml_format()
+        .parse()
+        .map_err(|e| anyhow!("Could not represent date {ts} in TOML: {e}"))
+}
+
 /// Turns a `toml::Value` into its `defaults_rs::PrefValue` counterpart.
 pub fn toml_to_prefvalue(val: &Value) -> Result<PrefValue> {
     Ok(match val {
@@ -30,12 +109,15 @@ pub fn toml_to_prefvalue(val: &Value) -> Result<PrefValue> {
                 .map(toml_to_prefvalue)
                 .collect::<Result<Vec<_>>>()?,
         ),
-        Value::Table(tbl) => PrefValue::Dictionary(
-            tbl.iter()
-                .map(|(k, v)| Ok((k.clone(), toml_to_prefvalue(v)?)))
-                .collect::<Result<HashMap<_, _>>>()?,
-        ),
-        _ => bail!("Unsupported TOML value for PrefValue"),
+        Value::Table(tbl) => match table_to_data(tbl) {
+            Some(bytes) => PrefValue::Data(bytes?.into_boxed_slice()),
+            None => PrefValue::Dictionary(
+                tbl.iter()
+                    .map(|(k, v)| Ok((k.clone(), toml_to_prefvalue(v)?)))
+                    .collect::<Result<HashMap<_, _>>>()?,
+            ),
+        },
+        Value::Datetime(dt) => PrefValue::Date(datetime_to_apple_secs(dt)?),
     })
 }
 
@@ -56,6 +138,12 @@ pub fn prefvalue_to_toml(val: &PrefValue) -> Result<Value> {
             .map(|(k, v)| Ok((k.clone(), prefvalue_to_toml(v)?)))
             .collect::<Result<toml::map::Map<_, _>>>()
             .map(Value::Table)?,
+        PrefValue::Date(ts) => Value::Datetime(apple_secs_to_datetime(*ts)?),
+        PrefValue::Data(bytes) => {
+            let mut tbl = toml::Table::new();
+            tbl.insert(DATA_KEY.to_string(), Value::String(BASE64.encode(bytes)));
+            Value::Table(tbl)
+        }
         _ => bail!("Support does not extend to complex types of data."),
     })
 }
@@ -100,14 +188,9 @@ pub fn toml_edit_to_prefvalue(val: &EditValue) -> anyhow::Result<PrefValue> {
             }
             PrefValue::Array(result)
         }
-        EditValue::InlineTable(tbl) => {
-            let mut dict = HashMap::new();
-            for (k, v) in tbl {
-                dict.insert(k.to_string(), toml_edit_to_prefvalue(v)?);
-            }
-            PrefValue::Dictionary(dict)
-        }
-        _ => bail!("Unsupported toml_edit value type for PrefValue"),
+        // may be a { data = "..." } table, so this goes through the same path as `toml::Value`
+        EditValue::InlineTable(_) => toml_to_prefvalue(&toml_edit_to_toml(val)?)?,
+        EditValue::Datetime(dt) => PrefValue::Date(datetime_to_apple_secs(dt.value())?),
     })
 }
 
@@ -132,10 +215,29 @@ pub fn toml_edit_to_toml(val: &EditValue) -> anyhow::Result<Value> {
             }
             Value::Table(map)
         }
-        _ => bail!("Unsupported toml_edit value type"),
+        EditValue::Datetime(dt) => Value::Datetime(*dt.value()),
     })
 }
 
+/// Converts a `toml::Value` to a `toml_edit::Value`, for writing into the config.
+/// Tables become inline tables.
+#[must_use]
+pub fn toml_to_toml_edit(val: &Value) -> EditValue {
+    match val {
+        Value::String(s) => EditValue::from(s.as_str()),
+        Value::Integer(i) => EditValue::from(*i),
+        Value::Float(f) => EditValue::from(*f),
+        Value::Boolean(b) => EditValue::from(*b),
+        Value::Datetime(dt) => EditValue::from(*dt),
+        Value::Array(arr) => EditValue::Array(arr.iter().map(toml_to_toml_edit).collect()),
+        Value::Table(tbl) => EditValue::InlineTable(
+            tbl.iter()
+                .map(|(k, v)| (k.as_str(), toml_to_toml_edit(v)))
+                .collect(),
+        ),
+    }
+}
+
 /// Converts a `PrefValue` to a `SerializablePrefValue`.
 pub fn prefvalue_to_serializable(val: &PrefValue) -> Result<SerializablePrefValue> {
     Ok(match val {
@@ -153,6 +255,10 @@ pub fn prefvalue_to_serializable(val: &PrefValue) -> Result<SerializablePrefValu
                 .map(|(k, v)| Ok((k.clone(), prefvalue_to_serializable(v)?)))
                 .collect::<Result<HashMap<_, _>>>()?,
         ),
+        PrefValue::Date(ts) => SerializablePrefValue::Date(SerializableDate { date: *ts }),
+        PrefValue::Data(bytes) => SerializablePrefValue::Data(SerializableData {
+            data: bytes.to_vec(),
+        }),
         _ => bail!("Unsupported PrefValue type"),
     })
 }
@@ -172,5 +278,117 @@ pub fn serializable_to_prefvalue(val: &SerializablePrefValue) -> PrefValue {
                 .map(|(k, v)| (k.clone(), serializable_to_prefvalue(v)))
                 .collect(),
         ),
+        SerializablePrefValue::Date(d) => PrefValue::Date(d.date),
+        SerializablePrefValue::Data(d) => PrefValue::Data(d.data.clone().into_boxed_slice()),
+    }
+}
+
+/// Seconds between the UNIX epoch and the Apple (Core Data) epoch, 2001-01-01.
+const APPLE_EPOCH_OFFSET: f64 = 978_307_200.0;
+
+/// Turns a plist date into seconds since the Apple epoch, which `PrefValue::Date` carries.
+fn plist_date_to_apple_secs(date: plist::Date) -> f64 {
+    let unix = match SystemTime::from(date).duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs_f64(),
+        Err(before) => -before.duration().as_secs_f64(),
+    };
+    unix - APPLE_EPOCH_OFFSET
+}
+
+/// Turns seconds since the Apple epoch into a plist date.
+fn apple_secs_to_plist_date(ts: f64) -> plist::Date {
+    let unix = ts + APPLE_EPOCH_OFFSET;
+    let time = if unix >= 0.0 {
+        UNIX_EPOCH + Duration::from_secs_f64(unix)
+    } else {
+        UNIX_EPOCH - Duration::from_secs_f64(-unix)
+    };
+    time.into()
+}
+
+/// Turns a `plist::Value` into its `defaults_rs::PrefValue` counterpart.
+pub fn plist_to_prefvalue(val: &plist::Value) -> Result<PrefValue> {
+    Ok(match val {
+        plist::Value::String(s) => PrefValue::String(s.clone()),
+        plist::Value::Integer(i) => match i.as_signed() {
+            Some(i) => PrefValue::Integer(i),
+            None => bail!("Unsigned plist integer {i} does not fit into a PrefValue."),
+        },
+        plist::Value::Real(f) => PrefValue::Float(*f),
+        plist::Value::Boolean(b) => PrefValue::Boolean(*b),
+        plist::Value::Data(d) => PrefValue::Data(d.clone().into_boxed_slice()),
+        plist::Value::Date(d) => PrefValue::Date(plist_date_to_apple_secs(*d)),
+        plist::Value::Uid(u) => PrefValue::Uid(u.get()),
+        plist::Value::Array(arr) => PrefValue::Array(
+            arr.iter()
+                .map(plist_to_prefvalue)
+                .collect::<Result<Vec<_>>>()?,
+        ),
+        plist::Value::Dictionary(dict) => PrefValue::Dictionary(
+            dict.iter()
+                .map(|(k, v)| Ok((k.clone(), plist_to_prefvalue(v)?)))
+                .collect::<Result<HashMap<_, _>>>()?,
+        ),
+        _ => bail!("Unsupported plist value for PrefValue"),
+    })
+}
+
+/// Turns a `defaults_rs::PrefValue` into its `plist::Value` counterpart.
+/// URLs and UUIDs have no plist representation of their own and are stored as strings.
+#[must_use]
+pub fn prefvalue_to_plist(val: &PrefValue) -> plist::Value {
+    match val {
+        PrefValue::String(s) | PrefValue::Url(s) | PrefValue::Uuid(s) => {
+            plist::Value::String(s.clone())
+        }
+        PrefValue::Integer(i) => plist::Value::Integer((*i).into()),
+        PrefValue::Float(f) => plist::Value::Real(*f),
+        PrefValue::Boolean(b) => plist::Value::Boolean(*b),
+        PrefValue::Data(d) => plist::Value::Data(d.to_vec()),
+        PrefValue::Date(ts) => plist::Value::Date(apple_secs_to_plist_date(*ts)),
+        PrefValue::Uid(u) => plist::Value::Uid(plist::Uid::new(*u)),
+        PrefValue::Array(arr) => plist::Value::Array(arr.iter().map(prefvalue_to_plist).collect()),
+        PrefValue::Dictionary(dict) => plist::Value::Dictionary(
+            dict.iter()
+                .map(|(k, v)| (k.clone(), prefvalue_to_plist(v)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dictionaries_with_date_or_data_keys_stay_dictionaries() -> Result<()> {
+        let dict = |key: &str, value: PrefValue| {
+            PrefValue::Dictionary(HashMap::from([(key.to_string(), value)]))
+        };
+
+        for value in [
+            dict("date", PrefValue::Integer(5)),
+            dict("data", PrefValue::String("AAECAw==".to_string())),
+            PrefValue::Date(5.0),
+            PrefValue::Data(vec![0, 1, 2, 3].into_boxed_slice()),
+        ] {
+            let json = serde_json::to_string(&prefvalue_to_serializable(&value)?)?;
+            let back: SerializablePrefValue = serde_json::from_str(&json)?;
+
+            assert_eq!(serializable_to_prefvalue(&back), value, "{json}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn config_data_tables_round_trip() -> Result<()> {
+        let table: toml::Table = toml::from_str("v = { data = \"AAECAw==\" }")?;
+        let data = PrefValue::Data(vec![0, 1, 2, 3].into_boxed_slice());
+
+        assert_eq!(toml_to_prefvalue(&table["v"])?, data);
+        assert_eq!(prefvalue_to_toml(&data)?, table["v"]);
+
+        Ok(())
     }
 }
@@ -1,20 +1,47 @@
-use anyhow::Result;
-use defaults_rs::{Domain, PrefValue, Preferences};
-use std::collections::{HashMap, HashSet};
-use toml::Table;
+use anyhow::{Context, Result, anyhow, bail};
+use defaults_rs::PrefValue;
+use regex::{Captures, Regex};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+};
+use toml::{Table, Value};
 use toml_edit::{DocumentMut, Item};
 
-use crate::domains::convert::toml_edit_to_toml;
+use crate::{
+    backend::{PreferencesBackend, to_domain},
+    config::{
+        vars::doc_vars,
+        when::{WHEN_KEY, When},
+    },
+    domains::convert::toml_edit_to_toml,
+};
 
 /// Collect all tables in `[set]`, parse with `toml_edit` to properly handle inline tables,
 /// and return a map domain → settings.
+///
+/// Domains whose `when` clause doesn't hold on this machine are left out, and `$var`/`${var}`
+/// in string values are expanded from `[vars]` and the environment.
 pub async fn collect(doc: &DocumentMut) -> Result<HashMap<String, Table>> {
-    let mut out = HashMap::new();
+    Ok(collect_split(doc)?.0)
+}
+
+/// Collect the tables in `[set]` whose `when` clause doesn't hold on this machine.
+pub async fn collect_inapplicable(doc: &DocumentMut) -> Result<HashMap<String, Table>> {
+    Ok(collect_split(doc)?.1)
+}
+
+/// Returns the applicable and inapplicable domains of `[set]`.
+type SplitDomains = (HashMap<String, Table>, HashMap<String, Table>);
+
+fn collect_split(doc: &DocumentMut) -> Result<SplitDomains> {
+    let mut out = (HashMap::new(), HashMap::new());
+    let vars = doc_vars(doc)?;
 
     if let Some(Item::Table(set)) = doc.get("set") {
         for (domain, item) in set {
             if let Item::Table(t) = item {
-                collect_table(domain, t, &mut out)?;
+                collect_table(domain, t, true, &vars, &mut out)?;
             }
         }
     }
@@ -22,51 +49,159 @@ pub async fn collect(doc: &DocumentMut) -> Result<HashMap<String, Table>> {
     Ok(out)
 }
 
+/// Expands `$var` and `${var}` in a string from `vars`, then the environment.
+///
+/// Unlike in commands, references which don't resolve are kept as they are (so values which
+/// merely contain a `$` stay untouched), and `$$` stands for a literal `$`.
+pub fn expand_vars(text: &str, vars: &HashMap<String, String>) -> Result<String> {
+    let re = Regex::new(r"\$\$|\$([A-Za-z_][A-Za-z0-9_]*)|\$\{([A-Za-z_][A-Za-z0-9_]*)\}")
+        .context("Failed to construct regex pattern for variable expansion.")?;
+
+    let result = re.replace_all(text, |caps: &Captures| {
+        let Some(name) = caps.get(1).or_else(|| caps.get(2)) else {
+            return "$".to_string();
+        };
+
+        vars.get(name.as_str())
+            .cloned()
+            .or_else(|| env::var(name.as_str()).ok())
+            .unwrap_or_else(|| caps[0].to_string())
+    });
+
+    Ok(result.into_owned())
+}
+
+/// Expands variables in every string of a value, including those nested in arrays and tables.
+pub fn interpolate(value: Value, vars: &HashMap<String, String>) -> Result<Value> {
+    Ok(match value {
+        Value::String(s) => Value::String(expand_vars(&s, vars)?),
+        Value::Array(arr) => Value::Array(
+            arr.into_iter()
+                .map(|v| interpolate(v, vars))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Table(tbl) => Value::Table(
+            tbl.into_iter()
+                .map(|(k, v)| Ok((k, interpolate(v, vars)?)))
+                .collect::<Result<_>>()?,
+        ),
+        other => other,
+    })
+}
+
 fn collect_table(
     domain: &str,
     table: &toml_edit::Table,
-    out: &mut HashMap<String, Table>,
+    applies: bool,
+    vars: &HashMap<String, String>,
+    out: &mut SplitDomains,
 ) -> Result<()> {
+    // nested domains inherit the condition
+    let applies = applies
+        && match table.get(WHEN_KEY) {
+            Some(when) => When::from_item(when)
+                .map_err(|e| anyhow!("Invalid condition for [set.{domain}]: {e}"))?
+                .applies(),
+            None => true,
+        };
     let mut settings = Table::new();
 
     for (key, item) in table {
         match item {
+            _ if key == WHEN_KEY => {}
             Item::Value(v) => {
-                settings.insert(key.to_string(), toml_edit_to_toml(v)?);
+                settings.insert(key.to_string(), interpolate(toml_edit_to_toml(v)?, vars)?);
             }
             Item::Table(t) => {
-                collect_table(&format!("{domain}.{key}"), t, out)?;
+                collect_table(&format!("{domain}.{key}"), t, applies, vars, out)?;
             }
             _ => {}
         }
     }
 
     if !settings.is_empty() {
+        let out = if applies { &mut out.0 } else { &mut out.1 };
         out.insert(domain.to_string(), settings);
     }
     Ok(())
 }
 
-/// Returns all system domains as strings.
-pub fn get_sys_domain_strings() -> Result<HashSet<String>> {
-    let doms: HashSet<String> = Preferences::list_domains()?
-        .iter()
-        .map(|f| f.to_string())
-        .collect();
+/// Collect all key lists in `[unset]` and return a map domain → keys.
+///
+/// Both `[unset] dock = ["autohide"]` and nested tables such as
+/// `[unset.menuextra] clock = ["Show24Hour"]` are accepted.
+pub async fn collect_unset(doc: &DocumentMut) -> Result<HashMap<String, Vec<String>>> {
+    let mut out = HashMap::new();
+
+    if let Some(Item::Table(unset)) = doc.get("unset") {
+        collect_unset_table(None, unset, &mut out)?;
+    }
 
-    Ok(doms)
+    Ok(out)
 }
 
+fn collect_unset_table(
+    prefix: Option<&str>,
+    table: &toml_edit::Table,
+    out: &mut HashMap<String, Vec<String>>,
+) -> Result<()> {
+    for (name, item) in table {
+        let domain = match prefix {
+            Some(prefix) => format!("{prefix}.{name}"),
+            None => name.to_string(),
+        };
+
+        match item {
+            Item::Table(t) => collect_unset_table(Some(&domain), t, out)?,
+            Item::Value(toml_edit::Value::Array(arr)) => {
+                let keys = arr
+                    .iter()
+                    .map(|k| match k.as_str() {
+                        Some(k) => Ok(k.to_string()),
+                        None => bail!("Keys in [unset.{domain}] must be strings."),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                out.entry(domain).or_default().extend(keys);
+            }
+            _ => bail!("Expected a list of keys for [unset.{domain}]."),
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns all system domains as strings.
+pub fn get_sys_domain_strings(prefs: &dyn PreferencesBackend) -> Result<HashSet<String>> {
+    prefs.list_domains()
+}
+
+/// Prefix marking a `[set]` or `[unset]` domain as current-host (ByHost), e.g. `[set.currenthost.screensaver]`.
+pub const CURRENT_HOST_PREFIX: &str = "currenthost.";
+
+/// Prefix marking a domain as a full bundle identifier, e.g. `[set.app."org.mozilla.firefox"]`.
+/// Such domains are used as-is, without the implied `com.apple.` prefix.
+pub const APP_PREFIX: &str = "app.";
+
 /// Given the TOML domain and key, figure out the true domain-key pair for targeting system domains.
-/// As an extra argument, this function also receives the current domains list for validation.
+/// The returned flag tells whether the domain is a current-host (ByHost) one.
 #[must_use]
-pub fn get_effective_sys_domain_key(domain: &str, key: &str) -> (String, String) {
+pub fn get_effective_sys_domain_key(domain: &str, key: &str) -> (String, String, bool) {
+    // currenthost.foo -> foo, for the current host only
+    let (domain, current_host) = match domain.strip_prefix(CURRENT_HOST_PREFIX) {
+        Some(rest) => (rest, true),
+        None => (domain, false),
+    };
+
     let dom = {
         if domain.strip_prefix("NSGlobalDomain.").is_some() {
             // NSGlobalDomain.foo -> NSGlobalDomain
             "NSGlobalDomain".into()
         } else if domain == "NSGlobalDomain" {
             domain.into()
+        } else if let Some(bundle_id) = domain.strip_prefix(APP_PREFIX) {
+            // app.org.mozilla.firefox -> org.mozilla.firefox
+            bundle_id.into()
         } else {
             // anything else gets com.apple.
             format!("com.apple.{domain}")
@@ -82,16 +217,37 @@ pub fn get_effective_sys_domain_key(domain: &str, key: &str) -> (String, String)
             key.into()
         }
     };
-    (dom, k)
+    (dom, k, current_host)
 }
 
 /// Read the current value of a defaults key, if any.
-pub async fn read_current(eff_domain: &str, eff_key: &str) -> Option<PrefValue> {
-    let domain_obj = if eff_domain == "NSGlobalDomain" {
-        Domain::Global
-    } else {
-        Domain::User(eff_domain.to_string())
-    };
+pub async fn read_current(
+    prefs: &dyn PreferencesBackend,
+    eff_domain: &str,
+    eff_key: &str,
+    current_host: bool,
+) -> Option<PrefValue> {
+    prefs
+        .read(&to_domain(eff_domain, current_host), eff_key)
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_vars_leaves_unresolved_references_alone() -> Result<()> {
+        let vars = HashMap::from([("name".to_string(), "darkstar".to_string())]);
 
-    (Preferences::read(domain_obj, eff_key)).ok()
+        assert_eq!(expand_vars("a$b", &vars)?, "a$b");
+        assert_eq!(
+            expand_vars("${cutler_unset_var}/x", &vars)?,
+            "${cutler_unset_var}/x"
+        );
+        assert_eq!(expand_vars("$name-${name}", &vars)?, "darkstar-darkstar");
+        assert_eq!(expand_vars("$$name costs $$5", &vars)?, "$name costs $5");
+
+        Ok(())
+    }
 }
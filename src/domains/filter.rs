@@ -0,0 +1,101 @@
+use clap::Args;
+
+use crate::domains::core::get_effective_sys_domain_key;
+
+/// `--only` / `--exclude` filters shared by the commands which touch preferences.
+///
+/// Each pattern is either a domain (`dock`, `com.apple.dock`, `NSGlobalDomain`) or a domain
+/// followed by a key (`dock.autohide`, `NSGlobalDomain.KeyRepeat`), and is matched against the
+/// effective domain-key pairs which cutler actually reads and writes. Domains can also be
+/// written the way the config does, e.g. `app."org.mozilla.firefox"` or `currenthost.screensaver`.
+#[derive(Args, Debug, Clone, Default)]
+pub struct DomainFilter {
+    /// Only touch the given domain or domain.key (can be repeated).
+    #[arg(long, value_name = "DOMAIN[.KEY]")]
+    pub only: Vec<String>,
+
+    /// Skip the given domain or domain.key (can be repeated).
+    #[arg(long, value_name = "DOMAIN[.KEY]")]
+    pub exclude: Vec<String>,
+}
+
+impl DomainFilter {
+    /// Whether any filter has been passed at all.
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        !self.only.is_empty() || !self.exclude.is_empty()
+    }
+
+    /// Decides whether an effective domain-key pair passes the filters.
+    #[must_use]
+    pub fn matches(&self, eff_domain: &str, eff_key: &str, current_host: bool) -> bool {
+        let matching = |p: &String| pattern_matches(p, eff_domain, eff_key, current_host);
+
+        (self.only.is_empty() || self.only.iter().any(matching))
+            && !self.exclude.iter().any(matching)
+    }
+}
+
+/// Matches a single pattern against an effective domain-key pair.
+///
+/// Besides full names, patterns are resolved the same way as config domains, so the implied
+/// `com.apple.` prefix may be left out. `currenthost.` patterns only match current-host pairs,
+/// while the others match both variants.
+fn pattern_matches(pattern: &str, eff_domain: &str, eff_key: &str, current_host: bool) -> bool {
+    // quotes as in app."org.mozilla.firefox" only group the name in the config
+    let pattern = pattern.replace('"', "");
+
+    let (dom, key, host_only) = get_effective_sys_domain_key(&pattern, "");
+    if host_only && !current_host {
+        return false;
+    }
+
+    // NSGlobalDomain.foo resolves to the key "foo.", which is put back together here
+    let resolved = match key.trim_end_matches('.') {
+        "" => dom,
+        key => format!("{dom}.{key}"),
+    };
+    let full = format!("{eff_domain}.{eff_key}");
+
+    [pattern, resolved].iter().any(|candidate| {
+        candidate == eff_domain
+            || *candidate == full
+            // dotted keys, e.g. NSGlobalDomain.com.apple.keyboard -> fnState
+            || (candidate.len() > eff_domain.len() && full.starts_with(&format!("{candidate}.")))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn only(pattern: &str) -> DomainFilter {
+        DomainFilter {
+            only: vec![pattern.to_string()],
+            exclude: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn patterns_resolve_like_config_domains() {
+        assert!(only("dock").matches("com.apple.dock", "autohide", false));
+        assert!(only("com.apple.dock.autohide").matches("com.apple.dock", "autohide", false));
+        assert!(only("app.\"org.mozilla.firefox\"").matches("org.mozilla.firefox", "Foo", false));
+        assert!(only("app.org.mozilla.firefox.Foo").matches("org.mozilla.firefox", "Foo", false));
+
+        assert!(only("NSGlobalDomain.KeyRepeat").matches("NSGlobalDomain", "KeyRepeat", false));
+        assert!(!only("NSGlobalDomain.KeyRepeat").matches(
+            "NSGlobalDomain",
+            "InitialKeyRepeat",
+            false
+        ));
+
+        assert!(only("screensaver").matches("com.apple.screensaver", "idleTime", true));
+        assert!(only("currenthost.screensaver").matches("com.apple.screensaver", "idleTime", true));
+        assert!(!only("currenthost.screensaver").matches(
+            "com.apple.screensaver",
+            "idleTime",
+            false
+        ));
+    }
+}
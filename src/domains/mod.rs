@@ -1,3 +1,9 @@
+pub mod catalog;
 pub mod convert;
 pub mod core;
-pub use core::{collect, read_current};
+pub mod filter;
+pub mod restart;
+pub mod spec;
+pub use core::{collect, collect_inapplicable, collect_unset, read_current};
+pub use filter::DomainFilter;
+pub use spec::ValueSpec;
@@ -0,0 +1,52 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::domains::{catalog::Catalog, core::get_effective_sys_domain_key};
+
+/// Built-in map of effective domains to the processes which have to be restarted
+/// for changes in them to take effect.
+const DEFAULT_RESTARTS: &[(&str, &[&str])] = &[
+    ("NSGlobalDomain", &["SystemUIServer", "Dock", "Finder"]),
+    ("com.apple.dock", &["Dock"]),
+    ("com.apple.spaces", &["Dock"]),
+    ("com.apple.finder", &["Finder"]),
+    ("com.apple.systemuiserver", &["SystemUIServer"]),
+    ("com.apple.screencapture", &["SystemUIServer"]),
+    (
+        "com.apple.menuextra.clock",
+        &["SystemUIServer", "ControlCenter"],
+    ),
+    ("com.apple.controlcenter", &["ControlCenter"]),
+    ("com.apple.notificationcenterui", &["NotificationCenter"]),
+    ("com.apple.WindowManager", &["WindowManager"]),
+];
+
+/// Decides which processes to restart after writing to the given effective domains.
+///
+/// Domains in the `[restart]` table are written the same way as in `[set]` (e.g. `dock`) and
+/// take precedence over the built-in map, so `dock = []` disables restarting the Dock.
+/// Domains missing from both fall back to the restarts listed in the preference catalog.
+#[must_use]
+pub fn services_for<'a>(
+    domains: impl IntoIterator<Item = &'a str>,
+    overrides: Option<&HashMap<String, Vec<String>>>,
+) -> Vec<String> {
+    let overrides: HashMap<String, &Vec<String>> = overrides
+        .into_iter()
+        .flatten()
+        .map(|(dom, services)| (get_effective_sys_domain_key(dom, "").0, services))
+        .collect();
+
+    let mut services = BTreeSet::new();
+
+    for domain in domains {
+        if let Some(custom) = overrides.get(domain) {
+            services.extend(custom.iter().cloned());
+        } else if let Some((_, builtin)) = DEFAULT_RESTARTS.iter().find(|(d, _)| *d == domain) {
+            services.extend(builtin.iter().map(|s| (*s).to_string()));
+        } else if let Ok(catalog) = Catalog::get() {
+            services.extend(catalog.restarts_for(domain).into_iter().map(String::from));
+        }
+    }
+
+    services.into_iter().collect()
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+use defaults_rs::PrefValue;
+use toml::Value;
+
+use crate::domains::convert::toml_to_prefvalue;
+
+/// Key which turns an inline table into a merge, e.g. `{ "$merge" = { ... } }`.
+///
+/// Like `$date`/`$data` in snapshots, the `$` keeps these apart from plain dictionaries,
+/// so a dictionary with a `merge` or `append` key is still written as it is.
+pub const MERGE_KEY: &str = "$merge";
+
+/// Keys which turn an inline table into array operations, e.g. `{ "$append" = [...] }`.
+pub const ARRAY_OP_KEYS: [&str; 3] = ["$append", "$remove", "$ensure_contains"];
+
+/// Operations on the current array, applied in the order of the fields.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ArrayOps {
+    /// Items which must not be present.
+    pub remove: Vec<PrefValue>,
+    /// Items which must be present anywhere; missing ones are added at the end.
+    pub ensure_contains: Vec<PrefValue>,
+    /// An alias of `ensure_contains`: missing items are added at the end, in this order,
+    /// while items already present are left where they are. Their position isn't checked.
+    pub append: Vec<PrefValue>,
+}
+
+impl ArrayOps {
+    /// Parses a table made up of `$append`, `$remove` and `$ensure_contains` lists.
+    fn from_table(tbl: &toml::Table) -> Result<Self> {
+        let mut ops = Self::default();
+
+        for (op, items) in tbl {
+            let Value::Array(items) = items else {
+                bail!("`{op}` expects a list of items.")
+            };
+            let items = items
+                .iter()
+                .map(toml_to_prefvalue)
+                .collect::<Result<Vec<_>>>()?;
+
+            match op.as_str() {
+                "$append" => ops.append = items,
+                "$remove" => ops.remove = items,
+                _ => ops.ensure_contains = items,
+            }
+        }
+
+        Ok(ops)
+    }
+
+    /// Applies the operations on top of the given items.
+    fn apply(&self, items: &mut Vec<PrefValue>) {
+        items.retain(|i| !self.remove.contains(i));
+
+        // never reorder existing items, which would fight with anything adding to the array
+        for item in self.ensure_contains.iter().chain(&self.append) {
+            if !items.contains(item) {
+                items.push(item.clone());
+            }
+        }
+    }
+}
+
+/// How a value configured under `[set]` relates to the one currently on the system.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueSpec {
+    /// Replace the current value entirely.
+    Replace(PrefValue),
+    /// Deep-merge the given sub-keys into the current dictionary, keeping the rest.
+    Merge(HashMap<String, PrefValue>),
+    /// Add or remove items of the current array, keeping the rest.
+    Array(ArrayOps),
+}
+
+impl ValueSpec {
+    /// Parses a configured value, recognizing the special inline table forms.
+    pub fn from_toml(val: &Value) -> Result<Self> {
+        if let Value::Table(tbl) = val
+            && let Some(merge) = tbl.get(MERGE_KEY)
+        {
+            if tbl.len() != 1 {
+                bail!("Merged values must look like: {{ \"$merge\" = {{ ... }} }}")
+            }
+
+            return match toml_to_prefvalue(merge)? {
+                PrefValue::Dictionary(dict) => Ok(Self::Merge(dict)),
+                _ => bail!("Only tables can be merged."),
+            };
+        }
+
+        if let Value::Table(tbl) = val
+            && tbl.keys().any(|k| ARRAY_OP_KEYS.contains(&k.as_str()))
+        {
+            if let Some(other) = tbl.keys().find(|k| !ARRAY_OP_KEYS.contains(&k.as_str())) {
+                bail!("`{other}` cannot be combined with array operations.")
+            }
+
+            return Ok(Self::Array(ArrayOps::from_table(tbl)?));
+        }
+
+        Ok(Self::Replace(toml_to_prefvalue(val)?))
+    }
+
+    /// Computes the value to write, given the one currently on the system.
+    #[must_use]
+    pub fn resolve(&self, current: Option<&PrefValue>) -> PrefValue {
+        match self {
+            Self::Replace(value) => value.clone(),
+            Self::Merge(sub) => {
+                let mut dict = match current {
+                    Some(PrefValue::Dictionary(dict)) => dict.clone(),
+                    _ => HashMap::new(),
+                };
+                deep_merge(&mut dict, sub);
+
+                PrefValue::Dictionary(dict)
+            }
+            Self::Array(ops) => {
+                let mut items = match current {
+                    Some(PrefValue::Array(items)) => items.clone(),
+                    _ => Vec::new(),
+                };
+                ops.apply(&mut items);
+
+                PrefValue::Array(items)
+            }
+        }
+    }
+
+    /// Whether the current value already satisfies the spec.
+    /// For merges and array operations, only the configured items are compared.
+    #[must_use]
+    pub fn is_satisfied(&self, current: Option<&PrefValue>) -> bool {
+        match (self, current) {
+            (_, Some(c)) => *c == self.resolve(current),
+            // nothing to remove from a missing array
+            (Self::Array(ops), None) => ops.append.is_empty() && ops.ensure_contains.is_empty(),
+            (_, None) => false,
+        }
+    }
+
+    /// Returns the stored and the desired type names if applying the spec would change
+    /// the type of the current value, e.g. `("integer", "boolean")`.
+    #[must_use]
+    pub fn type_change(&self, current: Option<&PrefValue>) -> Option<(&'static str, &'static str)> {
+        let current = current?;
+        let (from, to) = (current.get_type(), self.resolve(Some(current)).get_type());
+
+        (from != to).then_some((from, to))
+    }
+
+    /// A short, human-readable form of what is desired.
+    #[must_use]
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Replace(value) => value.to_string(),
+            Self::Merge(sub) => format!("{} (merged)", PrefValue::Dictionary(sub.clone())),
+            Self::Array(ops) => {
+                let contained = [ops.ensure_contains.clone(), ops.append.clone()].concat();
+
+                [("without", ops.remove.clone()), ("containing", contained)]
+                    .into_iter()
+                    .filter(|(_, items)| !items.is_empty())
+                    .map(|(label, items)| format!("{label} {}", PrefValue::Array(items)))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+        }
+    }
+}
+
+/// Merges `sub` into `dict`, recursing into dictionaries present on both sides.
+fn deep_merge(dict: &mut HashMap<String, PrefValue>, sub: &HashMap<String, PrefValue>) {
+    for (key, value) in sub {
+        match (dict.get_mut(key), value) {
+            (Some(PrefValue::Dictionary(existing)), PrefValue::Dictionary(nested)) => {
+                deep_merge(existing, nested);
+            }
+            _ => {
+                dict.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> Result<ValueSpec> {
+        let table: toml::Table = toml::from_str(&format!("v = {toml}"))?;
+        ValueSpec::from_toml(&table["v"])
+    }
+
+    #[test]
+    fn only_reserved_keys_make_a_spec() -> Result<()> {
+        for plain in [
+            "{ merge = true, value = { a = 1 } }",
+            "{ append = [1] }",
+            "{ remove = [1], ensure_contains = [2] }",
+        ] {
+            assert!(matches!(parse(plain)?, ValueSpec::Replace(_)), "{plain}");
+        }
+
+        assert!(matches!(
+            parse(r#"{ "$merge" = { a = 1 } }"#)?,
+            ValueSpec::Merge(_)
+        ));
+        assert!(matches!(
+            parse(r#"{ "$remove" = [1], "$append" = [2] }"#)?,
+            ValueSpec::Array(_)
+        ));
+        assert!(parse(r#"{ "$append" = [1], other = 2 }"#).is_err());
+
+        Ok(())
+    }
+}
@@ -1,7 +1,8 @@
 use crate::cli::atomic::should_dry_run;
 use crate::config::LoadedConfig;
+use crate::config::vars::resolve_vars;
 use crate::util::logging::{BOLD, RESET};
-use crate::{log_dry, log_exec, log_warn};
+use crate::{log_dry, log_exec, log_info, log_warn};
 use anyhow::{Context, Result, anyhow, bail};
 use regex::Regex;
 use std::collections::HashMap;
@@ -30,9 +31,14 @@ fn extract_cmd(config: &LoadedConfig, name: &str) -> Result<ExecJob> {
         .cloned()
         .ok_or_else(|| anyhow!("no such command {name}"))?;
 
+    if command.when.as_ref().is_some_and(|w| !w.applies()) {
+        bail!("Command {name} is not applicable on this machine.")
+    }
+
     // substitute to get possible variables
     // ultimately turning it into the final command to run
-    let run = substitute(&command.run, config.vars.clone())?;
+    let vars = resolve_vars(config.vars.as_ref())?;
+    let run = substitute(&command.run, Some(&vars))?;
 
     // extra fields
     let sudo = command.sudo.unwrap_or_default();
@@ -57,8 +63,9 @@ fn extract_all_cmds(config: &LoadedConfig) -> Vec<ExecJob> {
 
     if let Some(command_map) = config.command.as_ref() {
         for name in command_map.keys() {
-            if let Ok(job) = extract_cmd(config, name) {
-                jobs.push(job);
+            match extract_cmd(config, name) {
+                Ok(job) => jobs.push(job),
+                Err(e) => log_info!("Skipping command: {e}"),
             }
         }
     }
@@ -66,9 +73,9 @@ fn extract_all_cmds(config: &LoadedConfig) -> Vec<ExecJob> {
     jobs
 }
 
-/// Perform variable substitution (env + `[external.variables]`) in a text.
+/// Perform variable substitution (env + `[vars]`) in a text.
 /// Uses regex to find $var and ${var} patterns.
-fn substitute(text: &str, vars: Option<HashMap<String, String>>) -> Result<String> {
+fn substitute(text: &str, vars: Option<&HashMap<String, String>>) -> Result<String> {
     // regex to match $var or ${var}
     // $VAR_NAME or ${VAR_NAME}
     // note: $ followed by [A-Za-z_][A-Za-z0-9_]* or ${...}
@@ -79,8 +86,7 @@ fn substitute(text: &str, vars: Option<HashMap<String, String>>) -> Result<Strin
 
     // closure to resolve variable name
     let resolve_var = |var_name: &str| {
-        vars.as_ref()
-            .and_then(|map| map.get(var_name))
+        vars.and_then(|map| map.get(var_name))
             .cloned()
             .or_else(|| env::var(var_name).ok())
             .unwrap_or_else(|| format!("${{{var_name}}}"))
@@ -144,11 +150,39 @@ fn all_bins_present(required: &[String]) -> bool {
 }
 
 /// Execution mode enum.
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum ExecMode {
     Regular,
     All,
     Flagged,
+    /// Only the commands with the given names (e.g. from a saved plan).
+    Named(Vec<String>),
+}
+
+impl ExecMode {
+    /// Whether a job should be considered under this mode.
+    fn includes(&self, job: &ExecJob) -> bool {
+        match self {
+            Self::Regular => !job.flag,
+            Self::Flagged => job.flag,
+            Self::All => true,
+            Self::Named(names) => names.contains(&job.name),
+        }
+    }
+}
+
+/// Returns the name and final run text of each command considered under the given mode,
+/// sorted by name.
+#[must_use]
+pub fn list_cmds(config: &LoadedConfig, mode: &ExecMode) -> Vec<(String, String)> {
+    let mut cmds: Vec<(String, String)> = extract_all_cmds(config)
+        .into_iter()
+        .filter(|job| mode.includes(job))
+        .map(|job| (job.name, job.run))
+        .collect();
+
+    cmds.sort();
+    cmds
 }
 
 /// Run all extracted external commands via `sh -c` (or `sudo sh -c`) in parallel.
@@ -161,10 +195,7 @@ pub async fn run_all(config: LoadedConfig, mode: ExecMode) -> Result<i32> {
     let mut regular_cmds = Vec::new();
 
     for job in cmds {
-        if !all_bins_present(&job.required)
-            || (mode == ExecMode::Regular && job.flag)
-            || (mode == ExecMode::Flagged && !job.flag)
-        {
+        if !all_bins_present(&job.required) || !mode.includes(&job) {
             continue;
         } else if job.ensure_first {
             ensure_first_cmds.push(job);
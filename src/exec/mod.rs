@@ -1,3 +1,3 @@
 mod core;
 
-pub use core::{ExecMode, run_all, run_one};
+pub use core::{ExecMode, list_cmds, run_all, run_one};
@@ -1,4 +1,6 @@
+pub mod agent;
 pub mod autosync;
+pub mod backend;
 pub mod brew;
 pub mod cli;
 pub mod commands;
@@ -6,5 +8,7 @@ pub mod config;
 pub mod context;
 pub mod domains;
 pub mod exec;
+pub mod plan;
+pub mod report;
 pub mod snapshot;
 pub mod util;
@@ -7,6 +7,9 @@ use cutler::cli::Args;
 use cutler::cli::atomic::{
     set_accept_all, set_dry_run, set_no_restart_services, set_quiet, set_verbose,
 };
+use cutler::config::migrate::try_auto_migrate;
+use cutler::config::profile::set_profile;
+use cutler::config::vars::set_var_overrides;
 use cutler::context::AppContextManager;
 use cutler::util::sudo::{run_with_noroot, run_with_root};
 use cutler::{log_err, log_info};
@@ -23,11 +26,16 @@ async fn main() {
     set_dry_run(args.dry_run);
     set_no_restart_services(args.no_restart_services);
 
+    if let Some(profile) = &args.profile {
+        set_profile(profile.clone());
+    }
+    set_var_overrides(args.vars.clone());
+
     // create app context
     let ctx = match AppContextManager::sync().await {
         Ok(ctx) => ctx,
-        Err(_) => {
-            log_err!("App context failed to initialize for cutler.");
+        Err(e) => {
+            log_err!("App context failed to initialize for cutler: {e}");
             exit(1);
         }
     };
@@ -41,6 +49,12 @@ async fn main() {
         exit(1);
     }
 
+    // do template-check and terminate if true
+    if rules.respect_template && ctx.config.is_template().await {
+        log_err!("Config is a template. Review it, then run `cutler config activate` to use it.");
+        exit(1);
+    }
+
     // run remote-sync if command respects
     if args.no_sync {
         log_info!("Skipping remote config autosync.");
@@ -48,6 +62,11 @@ async fn main() {
         try_auto_sync(&ctx.config).await;
     }
 
+    // offer to migrate configs written for an older version, only for commands which load it
+    if rules.do_config_migrate {
+        try_auto_migrate(&ctx.config).await;
+    }
+
     // sudo protection
     if let Err(e) = if rules.require_sudo {
         run_with_root().await
@@ -0,0 +1,388 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use defaults_rs::PrefValue;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{
+    backend::to_domain,
+    brew::{types::BrewDiff, utils::diff_brew},
+    context::AppContext,
+    domains::{
+        DomainFilter, ValueSpec, collect, collect_unset,
+        convert::{SerializablePrefValue, prefvalue_to_serializable, serializable_to_prefvalue},
+        core::{get_effective_sys_domain_key, get_sys_domain_strings},
+        read_current,
+    },
+    log_info, log_warn,
+    snapshot::{
+        Snapshot,
+        core::{LoadedSnapshot, SettingState},
+    },
+    util::logging::redact_json,
+};
+
+/// Represents a preference modification job.
+#[derive(Debug, Clone)]
+pub struct PreferenceJob {
+    pub domain: String,
+    pub key: String,
+    pub current_host: bool,
+    /// The value to restore on unapply (carried over from the snapshot if already recorded).
+    pub original: Option<SerializablePrefValue>,
+    /// The value on the system right before this run.
+    pub current: Option<PrefValue>,
+    /// The value to write, or `None` if the key should be deleted (`[unset]`).
+    pub new_value: Option<PrefValue>,
+}
+
+/// Loads the snapshot which new jobs are merged into.
+///
+/// Falls back to an empty snapshot if there is none. If the existing one cannot be read,
+/// an empty snapshot is returned as well, alongside `true`.
+pub async fn load_snapshot_for_apply(snapshot: &Snapshot) -> (LoadedSnapshot, bool) {
+    if !snapshot.is_loadable() {
+        return (snapshot.new_empty(), false);
+    }
+
+    match snapshot.load().await {
+        Ok(snap) => (snap, false),
+        Err(e) => {
+            log_warn!("Bad snapshot: {e}; starting new.");
+            log_warn!("When unapplying, all your settings will reset to factory defaults.");
+
+            (snapshot.new_empty(), true)
+        }
+    }
+}
+
+/// Computes the jobs required to bring the system preferences in line with the config.
+///
+/// Changing the stored type of a key is warned about, or refused if `strict_types` is set.
+pub async fn compute_jobs(
+    ctx: &AppContext,
+    snap: &LoadedSnapshot,
+    is_bad_snap: bool,
+    filter: &DomainFilter,
+    no_dom_check: bool,
+    strict_types: bool,
+) -> Result<Vec<PreferenceJob>> {
+    let doc = ctx.config.load_as_mut().await?;
+    let config_system_domains = collect(&doc).await?;
+    let config_unset_domains = collect_unset(&doc).await?;
+
+    let mut jobs: Vec<PreferenceJob> = Vec::new();
+    let mut declared: HashSet<(String, String, bool)> = HashSet::new();
+    let prefs = ctx.prefs()?;
+    let system_domains: HashSet<String> = get_sys_domain_strings(prefs)?;
+
+    // turn the old snapshot into a hashmap for a quick lookup
+    let existing: HashMap<(String, String, bool), &SettingState> = snap
+        .settings
+        .iter()
+        .map(|s| ((s.domain.clone(), s.key.clone(), s.current_host), s))
+        .collect();
+
+    // system-specific domains
+    for (dom, keyval_table) in config_system_domains {
+        for (key, toml_value) in keyval_table {
+            let (eff_dom, eff_key, current_host) = get_effective_sys_domain_key(&dom, &key);
+            declared.insert((eff_dom.clone(), eff_key.clone(), current_host));
+
+            if !filter.matches(&eff_dom, &eff_key, current_host) {
+                log_info!("Skipping filtered {eff_dom} | {eff_key}");
+                continue;
+            }
+
+            // current-host domains are not listed alongside the regular ones
+            if !no_dom_check
+                && !current_host
+                && eff_dom != "NSGlobalDomain"
+                && !system_domains.contains(&eff_dom)
+            {
+                bail!(
+                    "Domain \"{eff_dom}\" was not found; cannot write to it. Disable this behavior by passing: --no-dom-check"
+                )
+            }
+
+            let current_pref = read_current(prefs, &eff_dom, &eff_key, current_host).await;
+            let spec = ValueSpec::from_toml(&toml_value)
+                .map_err(|e| anyhow!("Invalid value for {eff_dom} | {eff_key}: {e}"))?;
+
+            if !spec.is_satisfied(current_pref.as_ref()) {
+                if let Some((from, to)) = spec.type_change(current_pref.as_ref()) {
+                    if strict_types {
+                        bail!(
+                            "{eff_dom} | {eff_key} is stored as {from}, refusing to change it to {to} (--strict-types)."
+                        )
+                    }
+                    log_warn!("{eff_dom} | {eff_key} is stored as {from}; changing it to {to}.");
+                }
+
+                // merges and other partial forms are computed against the current value
+                let new_pref = spec.resolve(current_pref.as_ref());
+
+                jobs.push(PreferenceJob {
+                    original: if is_bad_snap {
+                        None
+                    } else {
+                        original_for(
+                            &existing,
+                            (&eff_dom, &eff_key, current_host),
+                            current_pref.as_ref(),
+                        )?
+                    },
+                    domain: eff_dom,
+                    key: eff_key,
+                    current_host,
+                    new_value: Some(new_pref),
+                    current: current_pref,
+                });
+            } else {
+                log_info!("Skipping unchanged {eff_dom} | {eff_key}",);
+            }
+        }
+    }
+
+    // keys which must not be set at all
+    for (dom, keys) in config_unset_domains {
+        for key in keys {
+            let (eff_dom, eff_key, current_host) = get_effective_sys_domain_key(&dom, &key);
+
+            if !filter.matches(&eff_dom, &eff_key, current_host) {
+                log_info!("Skipping filtered {eff_dom} | {eff_key}");
+                continue;
+            }
+
+            if declared.contains(&(eff_dom.clone(), eff_key.clone(), current_host)) {
+                bail!("{eff_dom} | {eff_key} is declared in both [set] and [unset].")
+            }
+
+            // only delete it if currently set
+            let Some(current_pref) = read_current(prefs, &eff_dom, &eff_key, current_host).await
+            else {
+                log_info!("Skipping already unset {eff_dom} | {eff_key}");
+                continue;
+            };
+
+            jobs.push(PreferenceJob {
+                original: if is_bad_snap {
+                    None
+                } else {
+                    original_for(
+                        &existing,
+                        (&eff_dom, &eff_key, current_host),
+                        Some(&current_pref),
+                    )?
+                },
+                domain: eff_dom,
+                key: eff_key,
+                current_host,
+                new_value: None,
+                current: Some(current_pref),
+            });
+        }
+    }
+
+    Ok(jobs)
+}
+
+/// Decides the value to restore on unapply for a key about to be modified.
+///
+/// An original already recorded in the snapshot is preserved;
+/// otherwise, for brand new keys, it is captured from the system.
+fn original_for(
+    existing: &HashMap<(String, String, bool), &SettingState>,
+    (eff_dom, eff_key, current_host): (&str, &str, bool),
+    current: Option<&PrefValue>,
+) -> Result<Option<SerializablePrefValue>> {
+    if let Some(e) = existing.get(&(eff_dom.to_string(), eff_key.to_string(), current_host)) {
+        Ok(e.original_value.clone())
+    } else if let Some(pref) = current {
+        Ok(Some(prefvalue_to_serializable(pref).with_context(
+            || format!("Failed to serialize current preference value for key '{eff_key}'."),
+        )?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// A single planned preference change.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PlannedPreference {
+    pub domain: String,
+    pub key: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub current_host: bool,
+    pub current: Option<SerializablePrefValue>,
+    /// `None` if the key is planned to be deleted.
+    pub desired: Option<SerializablePrefValue>,
+    pub original: Option<SerializablePrefValue>,
+}
+
+/// A single planned external command.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PlannedCommand {
+    pub name: String,
+    pub run: String,
+}
+
+/// Represents a saved execution plan.
+///
+/// A plan is computed by `cutler plan` and can be reviewed before being executed as-is
+/// through `cutler apply --plan`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Plan {
+    /// The cutler version which made the plan. Plans are only executed by the same version.
+    pub version: String,
+    /// Digest of the config as it resolved when planning, profile and variables included.
+    pub digest: String,
+    pub preferences: Vec<PlannedPreference>,
+    pub brew: Option<BrewDiff>,
+    pub commands: Vec<PlannedCommand>,
+}
+
+impl Plan {
+    /// Builds a plan out of computed jobs and the other pieces of an apply run.
+    pub fn new(
+        digest: String,
+        jobs: &[PreferenceJob],
+        brew: Option<BrewDiff>,
+        commands: Vec<PlannedCommand>,
+    ) -> Result<Self> {
+        let preferences = jobs
+            .iter()
+            .map(|job| {
+                Ok(PlannedPreference {
+                    domain: job.domain.clone(),
+                    key: job.key.clone(),
+                    current_host: job.current_host,
+                    current: job
+                        .current
+                        .as_ref()
+                        .map(prefvalue_to_serializable)
+                        .transpose()?,
+                    desired: job
+                        .new_value
+                        .as_ref()
+                        .map(prefvalue_to_serializable)
+                        .transpose()?,
+                    original: job.original.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .context("Failed to serialize preference values for plan.")?;
+
+        Ok(Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            digest,
+            preferences,
+            brew,
+            commands,
+        })
+    }
+
+    /// Loads a plan from the given path.
+    pub async fn load(path: &Path) -> Result<Self> {
+        let txt = fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Could not read plan file {path:?}."))?;
+
+        let plan: Self =
+            serde_json::from_str(&txt).with_context(|| format!("Invalid plan file {path:?}."))?;
+
+        let version = env!("CARGO_PKG_VERSION");
+        if plan.version != version {
+            bail!(
+                "Plan was made by cutler {}, but this is cutler {version}. Run `cutler plan` again.",
+                plan.version
+            )
+        }
+
+        Ok(plan)
+    }
+
+    /// Saves the plan into the given path.
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent()
+            && !dir.as_os_str().is_empty()
+        {
+            fs::create_dir_all(dir).await?;
+        }
+
+        fs::write(path, self.to_json()?).await?;
+        Ok(())
+    }
+
+    /// Returns the plan as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Returns the plan as pretty-printed JSON with secrets masked, for printing.
+    pub fn to_redacted_json(&self) -> Result<String> {
+        let mut value = serde_json::to_value(self)?;
+        redact_json(&mut value);
+
+        Ok(serde_json::to_string_pretty(&value)?)
+    }
+
+    /// Turns the planned preferences back into jobs.
+    #[must_use]
+    pub fn to_jobs(&self) -> Vec<PreferenceJob> {
+        self.preferences
+            .iter()
+            .map(|p| PreferenceJob {
+                domain: p.domain.clone(),
+                key: p.key.clone(),
+                current_host: p.current_host,
+                original: p.original.clone(),
+                current: p.current.as_ref().map(serializable_to_prefvalue),
+                new_value: p.desired.as_ref().map(serializable_to_prefvalue),
+            })
+            .collect()
+    }
+
+    /// Ensures that neither the config nor the system has changed since the plan was made.
+    pub async fn verify(&self, ctx: &AppContext) -> Result<()> {
+        if self.digest != ctx.config.resolved_digest().await? {
+            bail!("Config has been modified since the plan was made. Run `cutler plan` again.")
+        }
+
+        let prefs = ctx.prefs()?;
+        for p in &self.preferences {
+            let now = read_current(prefs, &p.domain, &p.key, p.current_host)
+                .await
+                .as_ref()
+                .map(prefvalue_to_serializable)
+                .transpose()?;
+
+            if now != p.current {
+                bail!(
+                    "{} | {} has changed since the plan was made. Run `cutler plan` again.",
+                    to_domain(&p.domain, p.current_host),
+                    p.key
+                )
+            }
+        }
+
+        if let Some(planned) = &self.brew {
+            let brew_cfg = ctx.config.load().await?.brew.unwrap_or_default();
+
+            if &diff_brew(brew_cfg).await? != planned {
+                bail!(
+                    "Homebrew state has changed since the plan was made. Run `cutler plan` again."
+                )
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,3 @@
+mod core;
+
+pub use core::*;
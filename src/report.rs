@@ -0,0 +1,324 @@
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use std::fmt::Write;
+
+use crate::{
+    backend::to_domain,
+    brew::{
+        types::BrewDiff,
+        utils::{brew_is_installed, diff_brew},
+    },
+    context::AppContext,
+    domains::{
+        DomainFilter, ValueSpec, collect, collect_inapplicable, collect_unset,
+        core::get_effective_sys_domain_key, read_current,
+    },
+    log_err, log_warn,
+    util::logging::redact,
+};
+
+/// Outcome of comparing a single preference against the config.
+#[derive(Serialize, Debug, Clone)]
+pub struct PrefOutcome {
+    pub domain: String,
+    pub key: String,
+    pub current_host: bool,
+    /// `None` if the key must not be set (`[unset]`).
+    /// Both values have secrets masked, as they only ever end up in the output.
+    pub desired: Option<String>,
+    pub current: Option<String>,
+    pub matched: bool,
+    /// Whether the domain's `when` clause holds on this machine.
+    /// Keys which aren't applicable never count as drift.
+    pub applicable: bool,
+    /// Set if applying the config would change the stored type of the key.
+    pub type_change: Option<TypeChange>,
+}
+
+/// The stored and desired types of a preference whose type would change.
+#[derive(Serialize, Debug, Clone)]
+pub struct TypeChange {
+    pub stored: &'static str,
+    pub desired: &'static str,
+}
+
+impl PrefOutcome {
+    /// The domain as shown to the user, marking current-host domains.
+    #[must_use]
+    pub fn domain_label(&self) -> String {
+        to_domain(&self.domain, self.current_host).to_string()
+    }
+
+    /// Whether the key has drifted from the config.
+    #[must_use]
+    pub fn drifted(&self) -> bool {
+        self.applicable && !self.matched
+    }
+}
+
+/// A full comparison of the system against the config, as done by `cutler status`.
+#[derive(Serialize, Debug, Default)]
+pub struct StatusReport {
+    pub preferences: Vec<PrefOutcome>,
+    pub brew: Option<BrewDiff>,
+}
+
+impl StatusReport {
+    /// Compares the system against the config.
+    /// Homebrew is only checked if `check_brew` is set and a [brew] table exists.
+    pub async fn gather(ctx: &AppContext, filter: &DomainFilter, check_brew: bool) -> Result<Self> {
+        let doc = ctx.config.load_as_mut().await?;
+        let domains = collect(&doc).await?;
+        let inapplicable = collect_inapplicable(&doc).await?;
+        let unset_domains = collect_unset(&doc).await?;
+        let prefs = ctx.prefs()?;
+
+        let mut preferences = Vec::new();
+
+        let all_domains = domains
+            .into_iter()
+            .map(|d| (d, true))
+            .chain(inapplicable.into_iter().map(|d| (d, false)));
+
+        for ((dom, table), applicable) in all_domains {
+            for (key, value) in table {
+                let (eff_dom, eff_key, current_host) = get_effective_sys_domain_key(&dom, &key);
+
+                if !filter.matches(&eff_dom, &eff_key, current_host) {
+                    continue;
+                }
+
+                let current_pref = read_current(prefs, &eff_dom, &eff_key, current_host).await;
+                let spec = ValueSpec::from_toml(&value)
+                    .map_err(|e| anyhow!("Invalid value for {eff_dom} | {eff_key}: {e}"))?;
+
+                preferences.push(PrefOutcome {
+                    matched: spec.is_satisfied(current_pref.as_ref()),
+                    applicable,
+                    type_change: spec
+                        .type_change(current_pref.as_ref())
+                        .map(|(stored, desired)| TypeChange { stored, desired }),
+                    current: current_pref.map(|c| redact(&c.to_string())),
+                    desired: Some(redact(&spec.describe())),
+                    domain: eff_dom,
+                    key: eff_key,
+                    current_host,
+                });
+            }
+        }
+
+        for (dom, keys) in unset_domains {
+            for key in keys {
+                let (eff_dom, eff_key, current_host) = get_effective_sys_domain_key(&dom, &key);
+
+                if !filter.matches(&eff_dom, &eff_key, current_host) {
+                    continue;
+                }
+
+                let current_pref = read_current(prefs, &eff_dom, &eff_key, current_host).await;
+
+                preferences.push(PrefOutcome {
+                    matched: current_pref.is_none(),
+                    applicable: true,
+                    type_change: None,
+                    current: current_pref.map(|c| redact(&c.to_string())),
+                    desired: None,
+                    domain: eff_dom,
+                    key: eff_key,
+                    current_host,
+                });
+            }
+        }
+
+        // keep domains grouped and the output stable across runs
+        preferences.sort_by(|a, b| {
+            (a.current_host, &a.domain, &a.key).cmp(&(b.current_host, &b.domain, &b.key))
+        });
+
+        let brew = match ctx.config.load().await?.brew {
+            Some(brew_cfg) if check_brew => {
+                if brew_is_installed().await {
+                    match diff_brew(brew_cfg).await {
+                        Ok(diff) => Some(diff),
+                        Err(e) => {
+                            log_err!("Could not check Homebrew status: {e}",);
+                            None
+                        }
+                    }
+                } else {
+                    log_warn!("Homebrew not available in $PATH, skipping status check for it.",);
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        Ok(Self { preferences, brew })
+    }
+
+    /// Whether any preference or Homebrew state has diverged from the config.
+    #[must_use]
+    pub fn has_drift(&self) -> bool {
+        self.preferences.iter().any(PrefOutcome::drifted)
+            || self
+                .brew_checks()
+                .iter()
+                .any(|(_, items)| !items.is_empty())
+    }
+
+    /// Labelled Homebrew lists, empty if Homebrew was not checked.
+    #[must_use]
+    pub fn brew_checks(&self) -> Vec<(&'static str, &[String])> {
+        match &self.brew {
+            Some(diff) => vec![
+                ("missing_formulae", &diff.missing_formulae),
+                ("extra_formulae", &diff.extra_formulae),
+                ("missing_casks", &diff.missing_casks),
+                ("extra_casks", &diff.extra_casks),
+                ("missing_taps", &diff.missing_taps),
+                ("extra_taps", &diff.extra_taps),
+            ],
+            None => Vec::new(),
+        }
+    }
+
+    /// Renders the report as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        #[derive(Serialize)]
+        struct JsonReport<'a> {
+            drift: bool,
+            #[serde(flatten)]
+            report: &'a StatusReport,
+        }
+
+        Ok(serde_json::to_string_pretty(&JsonReport {
+            drift: self.has_drift(),
+            report: self,
+        })?)
+    }
+
+    /// Renders the report as a JUnit XML document.
+    #[must_use]
+    pub fn to_junit(&self) -> String {
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let brew_checks = self.brew_checks();
+
+        let pref_failures = self.preferences.iter().filter(|p| p.drifted()).count();
+        let brew_failures = brew_checks.iter().filter(|(_, i)| !i.is_empty()).count();
+
+        let _ = writeln!(
+            out,
+            "<testsuites name=\"cutler\" tests=\"{}\" failures=\"{}\">",
+            self.preferences.len() + brew_checks.len(),
+            pref_failures + brew_failures
+        );
+
+        let _ = writeln!(
+            out,
+            "  <testsuite name=\"preferences\" tests=\"{}\" failures=\"{pref_failures}\">",
+            self.preferences.len()
+        );
+        for p in &self.preferences {
+            let _ = write!(
+                out,
+                "    <testcase classname=\"{}\" name=\"{}\"",
+                xml_escape(&p.domain_label()),
+                xml_escape(&p.key)
+            );
+            if !p.applicable {
+                out.push_str(">\n      <skipped message=\"not applicable\"/>\n    </testcase>\n");
+            } else if p.matched {
+                out.push_str("/>\n");
+            } else {
+                let _ = writeln!(
+                    out,
+                    ">\n      <failure message=\"should be {} (now: {})\"/>\n    </testcase>",
+                    xml_escape(p.desired.as_deref().unwrap_or("Not set")),
+                    xml_escape(p.current.as_deref().unwrap_or("Not set"))
+                );
+            }
+        }
+        out.push_str("  </testsuite>\n");
+
+        if !brew_checks.is_empty() {
+            let _ = writeln!(
+                out,
+                "  <testsuite name=\"brew\" tests=\"{}\" failures=\"{brew_failures}\">",
+                brew_checks.len()
+            );
+            for (label, items) in &brew_checks {
+                if items.is_empty() {
+                    let _ = writeln!(out, "    <testcase classname=\"brew\" name=\"{label}\"/>");
+                } else {
+                    let _ = writeln!(
+                        out,
+                        "    <testcase classname=\"brew\" name=\"{label}\">\n      <failure message=\"{}\"/>\n    </testcase>",
+                        xml_escape(&items.join(", "))
+                    );
+                }
+            }
+            out.push_str("  </testsuite>\n");
+        }
+
+        out.push_str("</testsuites>");
+        out
+    }
+
+    /// Renders the report in the Test Anything Protocol (TAP) format.
+    #[must_use]
+    pub fn to_tap(&self) -> String {
+        let brew_checks = self.brew_checks();
+        let mut out = format!(
+            "TAP version 13\n1..{}\n",
+            self.preferences.len() + brew_checks.len()
+        );
+        let mut n = 0;
+
+        for p in &self.preferences {
+            n += 1;
+            if !p.applicable {
+                let _ = writeln!(
+                    out,
+                    "ok {n} - {} {} # SKIP not applicable",
+                    p.domain_label(),
+                    p.key
+                );
+            } else if p.matched {
+                let _ = writeln!(out, "ok {n} - {} {}", p.domain_label(), p.key);
+            } else {
+                let _ = writeln!(
+                    out,
+                    "not ok {n} - {} {}\n  ---\n  desired: {}\n  current: {}\n  ...",
+                    p.domain_label(),
+                    p.key,
+                    p.desired.as_deref().unwrap_or("Not set"),
+                    p.current.as_deref().unwrap_or("Not set")
+                );
+            }
+        }
+
+        for (label, items) in &brew_checks {
+            n += 1;
+            if items.is_empty() {
+                let _ = writeln!(out, "ok {n} - brew {label}");
+            } else {
+                let _ = writeln!(
+                    out,
+                    "not ok {n} - brew {label}\n  ---\n  items: {}\n  ...",
+                    items.join(", ")
+                );
+            }
+        }
+
+        out.trim_end().to_string()
+    }
+}
+
+/// Escapes text for use inside XML attributes.
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -12,6 +12,9 @@ use crate::domains::convert::SerializablePrefValue;
 pub struct SettingState {
     pub domain: String,
     pub key: String,
+    /// Whether the setting lives in the current-host (ByHost) variant of the domain.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub current_host: bool,
     pub original_value: Option<SerializablePrefValue>,
 }
 
@@ -3,7 +3,7 @@ use tokio::process::Command;
 
 use crate::{
     cli::atomic::{should_accept_all, should_dry_run, should_not_restart_services},
-    log_dry, log_err, log_info, log_prompt, log_warn,
+    log_cute, log_dry, log_err, log_info, log_prompt, log_warn,
 };
 use anyhow::{Context, Result};
 
@@ -32,31 +32,30 @@ pub async fn open(arg: &str) -> Result<()> {
     Ok(())
 }
 
-/// Restart Finder, Dock, `SystemUIServer` so defaults take effect.
-pub async fn restart_services() {
+/// Restart the given services (e.g. Dock, Finder) so defaults take effect.
+/// The ones which are picked depend on the domains written; see `domains::restart`.
+pub async fn restart_services(services: &[String]) {
     if should_not_restart_services() {
         return;
     }
 
-    let dry_run = should_dry_run();
+    if services.is_empty() {
+        log_info!("No services need to be restarted.");
+        return;
+    }
 
-    // services to restart
-    const SERVICES: &[&str] = &[
-        "SystemUIServer",
-        "Dock",
-        "Finder",
-        "ControlCenter",
-        "NotificationCenter",
-    ];
+    let dry_run = should_dry_run();
 
     let mut failed: bool = false;
+    let mut restarted = Vec::new();
 
-    for svc in SERVICES {
+    for svc in services {
         if dry_run {
             log_dry!("Would restart {svc}");
         } else if let Ok(out) = Command::new("killall").arg(svc).output().await {
             if out.status.success() {
                 log_info!("{svc} restarted");
+                restarted.push(svc.as_str());
             } else {
                 log_err!("Failed to restart {svc}");
                 failed = true;
@@ -67,6 +66,10 @@ pub async fn restart_services() {
         }
     }
 
+    if !restarted.is_empty() {
+        log_cute!("Restarted {}.", restarted.join(", "));
+    }
+
     if failed {
         log_warn!("Being quick with commands can cause your computer to run out of breath.");
     }
@@ -1,6 +1,9 @@
 //! Logging module for cutler.
 //!
 //! Use the log_*! macros for pretty-printing text inside cutler.
+//! Values registered with `register_secret()` are masked in everything they print.
+
+use std::sync::{Mutex, PoisonError};
 
 use crate::cli::atomic::{should_be_quiet, should_be_verbose};
 
@@ -26,6 +29,46 @@ pub enum LogLevel {
     Fruitful, // 🍎
 }
 
+/// What secret values are replaced with in the output.
+pub const MASK: &str = "********";
+
+/// Values of secret variables, which are never printed.
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Registers a secret value to be masked in all log output.
+pub fn register_secret(value: &str) {
+    if value.is_empty() {
+        return;
+    }
+
+    let mut secrets = SECRETS.lock().unwrap_or_else(PoisonError::into_inner);
+    if !secrets.iter().any(|s| s == value) {
+        secrets.push(value.to_string());
+        // mask longer secrets first, in case one contains another
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+    }
+}
+
+/// Replaces every registered secret in a text with the mask.
+#[must_use]
+pub fn redact(text: &str) -> String {
+    let secrets = SECRETS.lock().unwrap_or_else(PoisonError::into_inner);
+
+    secrets.iter().fold(text.to_string(), |text, secret| {
+        text.replace(secret.as_str(), MASK)
+    })
+}
+
+/// Masks every registered secret in the strings of a JSON value, in place.
+pub fn redact_json(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(s) => *s = redact(s),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_json),
+        serde_json::Value::Object(map) => map.values_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
 #[doc(hidden)]
 pub fn _print_log(level: LogLevel, msg: &str) {
     if (should_be_quiet() && level != LogLevel::Error && level != LogLevel::Warning)
@@ -34,6 +77,8 @@ pub fn _print_log(level: LogLevel, msg: &str) {
         return;
     }
 
+    let msg = redact(msg);
+
     let (tag, color) = match level {
         LogLevel::Error => ("ERR  ", RED),
         LogLevel::Warning => ("WARN ", ORANGE),
@@ -119,3 +164,27 @@ macro_rules! log_prompt {
         $crate::util::logging::_print_log($crate::util::logging::LogLevel::Prompt, &msg);
     }};
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_json_masks_nested_strings() {
+        register_secret("cutler-test-secret");
+
+        let mut value = serde_json::json!({
+            "desired": "token=cutler-test-secret",
+            "nested": [{ "$data": "cutler-test-secret" }, 1, true],
+        });
+        redact_json(&mut value);
+
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "desired": format!("token={MASK}"),
+                "nested": [{ "$data": MASK }, 1, true],
+            })
+        );
+    }
+}
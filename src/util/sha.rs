@@ -3,7 +3,7 @@ use sha2::{Digest, Sha256};
 use std::{
     fs::File,
     io::{BufReader, Read},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 /// Gets the SHA256 digest of a file, given its path.
@@ -25,3 +25,32 @@ pub fn get_digest(path: &Path) -> Result<String> {
 
     Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
 }
+
+/// Gets a single SHA256 digest covering several files, in order.
+/// For a single file, this is the same as `get_digest`.
+pub fn get_combined_digest(paths: &[PathBuf]) -> Result<String> {
+    if let [path] = paths {
+        return get_digest(path);
+    }
+
+    let mut hasher = Sha256::new();
+    for path in paths {
+        hasher.update(get_digest(path)?.as_bytes());
+    }
+    let digest = hasher.finalize();
+
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Gets a single SHA256 digest covering several strings, in order.
+/// Each string is hashed on its own first, so their boundaries can't shift.
+#[must_use]
+pub fn get_parts_digest(parts: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(Sha256::digest(part.as_bytes()));
+    }
+    let digest = hasher.finalize();
+
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}